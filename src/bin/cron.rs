@@ -0,0 +1,25 @@
+use log::Level;
+use log::{error, info};
+use tokio::runtime::Runtime;
+
+use kliversala_bot::process_posts;
+
+fn main() {
+    simple_logger::init_with_level(Level::Info).expect("Failed to init logger");
+    let mut rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        match process_posts().await {
+            Ok(summary) if summary.failures.is_empty() => {
+                info!("successfully processed posts: {}", summary)
+            }
+            Ok(summary) => {
+                error!("some posts could not be processed: {}", summary);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                error!("error occurred while processing posts: {}", e);
+                std::process::exit(1);
+            }
+        }
+    });
+}
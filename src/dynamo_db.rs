@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use async_trait::async_trait;
 use log::{debug, error, info};
 use rusoto_core::{Region, RusotoError};
 use rusoto_dynamodb::{
@@ -7,7 +8,9 @@ use rusoto_dynamodb::{
     GetItemInput, PutItemError, PutItemInput, ScanError, ScanInput,
 };
 
-use crate::sources::{Image, Post};
+use crate::error::Error;
+use crate::sources::{image_key, Image, Post, PostKind, PostStatus};
+use crate::store::PostStore;
 
 pub struct DynamoClient {
     client: DynamoDbClient,
@@ -20,7 +23,7 @@ impl DynamoClient {
         DynamoClient { client, table_name }
     }
 
-    pub async fn get_post<'a>(&self, id: &str) -> Result<Option<Post>, RusotoError<GetItemError>> {
+    pub async fn get_post(&self, id: &str) -> Result<Option<Post>, RusotoError<GetItemError>> {
         let mut query_key: HashMap<String, AttributeValue> = HashMap::new();
         query_key.insert(
             String::from("id"),
@@ -64,7 +67,7 @@ impl DynamoClient {
                 ..Default::default()
             },
         );
-        if &post.text != "" {
+        if !post.text.is_empty() {
             query_key.insert(
                 String::from("text"),
                 AttributeValue {
@@ -74,7 +77,7 @@ impl DynamoClient {
             );
         }
         if let Some(tg_id) = &post.tg_id {
-            if tg_id != "" {
+            if !tg_id.is_empty() {
                 query_key.insert(
                     String::from("message_id"),
                     AttributeValue {
@@ -84,28 +87,137 @@ impl DynamoClient {
                 );
             }
         }
-        if post.images.len() > 0 {
+        if !post.images.is_empty() {
             let mut urls = vec![];
+            let mut keys = vec![];
+            let mut thumbnails = vec![];
             let mut ids = vec![];
 
+            // Lists rather than string sets, as sets neither keep the order of the images
+            // nor allow an image without a message id.
             for image in &post.images {
-                urls.push(String::from(&image.url));
-                if let Some(val) = &image.tg_id {
-                    ids.push(val.into());
-                }
+                urls.push(AttributeValue {
+                    s: Some(image.url.clone()),
+                    ..Default::default()
+                });
+                keys.push(AttributeValue {
+                    s: Some(image.key.clone()),
+                    ..Default::default()
+                });
+                thumbnails.push(match &image.thumbnail_url {
+                    Some(val) => AttributeValue {
+                        s: Some(val.clone()),
+                        ..Default::default()
+                    },
+                    None => AttributeValue {
+                        null: Some(true),
+                        ..Default::default()
+                    },
+                });
+                ids.push(match &image.tg_id {
+                    Some(val) => AttributeValue {
+                        s: Some(val.clone()),
+                        ..Default::default()
+                    },
+                    None => AttributeValue {
+                        null: Some(true),
+                        ..Default::default()
+                    },
+                });
             }
 
             query_key.insert(
                 String::from("images"),
                 AttributeValue {
-                    ss: Some(urls.clone()),
+                    l: Some(urls),
+                    ..Default::default()
+                },
+            );
+            query_key.insert(
+                String::from("image_keys"),
+                AttributeValue {
+                    l: Some(keys),
+                    ..Default::default()
+                },
+            );
+            query_key.insert(
+                String::from("image_thumbnails"),
+                AttributeValue {
+                    l: Some(thumbnails),
                     ..Default::default()
                 },
             );
             query_key.insert(
                 String::from("image_ids"),
                 AttributeValue {
-                    ss: Some(ids.clone()),
+                    l: Some(ids),
+                    ..Default::default()
+                },
+            );
+        }
+        if !post.continuation_ids.is_empty() {
+            query_key.insert(
+                String::from("continuation_ids"),
+                AttributeValue {
+                    l: Some(
+                        post.continuation_ids
+                            .iter()
+                            .map(|id| AttributeValue {
+                                s: Some(id.clone()),
+                                ..Default::default()
+                            })
+                            .collect(),
+                    ),
+                    ..Default::default()
+                },
+            );
+        }
+        query_key.insert(
+            String::from("status"),
+            AttributeValue {
+                s: Some(String::from(post.status.as_str())),
+                ..Default::default()
+            },
+        );
+        if let Some(published) = post.published {
+            query_key.insert(
+                String::from("published"),
+                AttributeValue {
+                    n: Some(published.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+        query_key.insert(
+            String::from("kind"),
+            AttributeValue {
+                s: Some(String::from(post.kind.as_str())),
+                ..Default::default()
+            },
+        );
+        if !post.source.is_empty() {
+            query_key.insert(
+                String::from("source"),
+                AttributeValue {
+                    s: Some(post.source.clone()),
+                    ..Default::default()
+                },
+            );
+        }
+        if let Some(permalink) = &post.permalink {
+            query_key.insert(
+                String::from("permalink"),
+                AttributeValue {
+                    s: Some(permalink.clone()),
+                    ..Default::default()
+                },
+            );
+        }
+        if let Some(author) = &post.author {
+            query_key.insert(
+                String::from("author"),
+                AttributeValue {
+                    s: Some(author.clone()),
                     ..Default::default()
                 },
             );
@@ -128,31 +240,49 @@ impl DynamoClient {
         }
     }
 
-    pub async fn scan_posts(&self) -> Result<Vec<Post>, RusotoError<ScanError>> {
-        let scan_input = ScanInput {
-            table_name: self.table_name.clone(),
-            ..ScanInput::default()
-        };
+    // A scan returns at most 1 MB of items at a time, the rest is read from where it stopped.
+    // The filter only cuts down what is sent back, the scan still reads the whole table.
+    pub async fn scan_posts(
+        &self,
+        published_since: u64,
+    ) -> Result<Vec<Post>, RusotoError<ScanError>> {
+        let mut posts = vec![];
+        let mut exclusive_start_key = None;
+        let mut values: HashMap<String, AttributeValue> = HashMap::new();
+        values.insert(
+            String::from(":since"),
+            AttributeValue {
+                n: Some(published_since.to_string()),
+                ..Default::default()
+            },
+        );
+        loop {
+            let scan_input = ScanInput {
+                table_name: self.table_name.clone(),
+                exclusive_start_key,
+                filter_expression: Some(String::from(
+                    "attribute_not_exists(published) OR published >= :since",
+                )),
+                expression_attribute_values: Some(values.clone()),
+                ..ScanInput::default()
+            };
 
-        match self.client.scan(scan_input).await {
-            Ok(res) => {
-                info!("scan: Ok(count: {:?})", res.count);
-                match res.items {
-                    Some(result) => {
-                        let mut posts = vec![];
-                        for entry in result {
-                            debug!("{:?}", entry);
-                            let post = build_post(entry);
-                            posts.push(post);
-                        }
-                        Ok(posts)
+            match self.client.scan(scan_input).await {
+                Ok(res) => {
+                    info!("scan: Ok(count: {:?})", res.count);
+                    for entry in res.items.unwrap_or_default() {
+                        debug!("{:?}", entry);
+                        posts.push(build_post(entry));
+                    }
+                    match res.last_evaluated_key {
+                        Some(key) if !key.is_empty() => exclusive_start_key = Some(key),
+                        _ => return Ok(posts),
                     }
-                    None => Ok(vec![]),
                 }
-            }
-            Err(error) => {
-                error!("scan: Error: {:?}", error);
-                Err(error)
+                Err(error) => {
+                    error!("scan: Error: {:?}", error);
+                    return Err(error);
+                }
             }
         }
     }
@@ -185,27 +315,69 @@ impl DynamoClient {
     }
 }
 
-fn build_post(entry: std::collections::HashMap<String, AttributeValue>) -> Post {
-    let image_urls = if let Some(val) = entry.get("images") {
-        val.ss.as_ref().unwrap().clone()
-    } else {
-        vec![]
-    };
+#[async_trait]
+impl PostStore for DynamoClient {
+    async fn get_post(&self, id: &str) -> Result<Option<Post>, Error> {
+        Ok(DynamoClient::get_post(self, id).await?)
+    }
 
-    let image_ids = if let Some(val) = entry.get("image_ids") {
-        val.ss.as_ref().unwrap().clone()
-    } else {
-        vec![]
-    };
+    async fn put_post(&self, post: &Post) -> Result<(), Error> {
+        Ok(DynamoClient::put_post(self, post).await?)
+    }
 
-    let mut images = Vec::new();
-    for (url, tg_id) in image_urls.iter().zip(image_ids.iter()) {
-        let image = Image {
-            url: url.into(),
-            tg_id: Some(tg_id.into()),
-        };
-        images.push(image);
+    async fn scan_posts(&self, published_since: u64) -> Result<Vec<Post>, Error> {
+        Ok(DynamoClient::scan_posts(self, published_since).await?)
+    }
+
+    async fn delete_post(&self, id: &str) -> Result<(), Error> {
+        Ok(DynamoClient::delete_post(self, id).await?)
     }
+}
+
+fn build_post(entry: std::collections::HashMap<String, AttributeValue>) -> Post {
+    let images = match (entry.get("images"), entry.get("image_ids")) {
+        (Some(AttributeValue { l: Some(urls), .. }), ids) => {
+            let ids = ids.and_then(|val| val.l.as_ref());
+            let keys = entry.get("image_keys").and_then(|val| val.l.as_ref());
+            let thumbnails = entry.get("image_thumbnails").and_then(|val| val.l.as_ref());
+            urls.iter()
+                .enumerate()
+                .map(|(index, url)| {
+                    let url = String::from(url.s.as_ref().unwrap());
+                    // Images stored before keys were kept get theirs from the URL.
+                    let key = keys
+                        .and_then(|keys| keys.get(index))
+                        .and_then(|key| key.s.clone())
+                        .unwrap_or_else(|| image_key(&url));
+                    Image {
+                        url,
+                        thumbnail_url: thumbnails
+                            .and_then(|thumbnails| thumbnails.get(index))
+                            .and_then(|thumbnail| thumbnail.s.clone()),
+                        key,
+                        tg_id: ids
+                            .and_then(|ids| ids.get(index))
+                            .and_then(|id| id.s.clone()),
+                    }
+                })
+                .collect()
+        }
+        // Posts stored before images were kept in lists use string sets.
+        (
+            Some(AttributeValue { ss: Some(urls), .. }),
+            Some(AttributeValue { ss: Some(ids), .. }),
+        ) => urls
+            .iter()
+            .zip(ids.iter())
+            .map(|(url, tg_id)| Image {
+                url: url.into(),
+                thumbnail_url: None,
+                key: image_key(url),
+                tg_id: Some(tg_id.into()),
+            })
+            .collect(),
+        _ => vec![],
+    };
 
     let text = if let Some(val) = entry.get("text") {
         val.s.as_ref().unwrap()
@@ -213,16 +385,205 @@ fn build_post(entry: std::collections::HashMap<String, AttributeValue>) -> Post
         ""
     };
 
-    let tg_id = if let Some(val) = entry.get("message_id") {
-        Some(String::from(val.s.as_ref().unwrap()))
-    } else {
-        None
-    };
+    let tg_id = entry
+        .get("message_id")
+        .map(|val| String::from(val.s.as_ref().unwrap()));
+
+    let continuation_ids = entry
+        .get("continuation_ids")
+        .and_then(|val| val.l.as_ref())
+        .map(|ids| ids.iter().filter_map(|id| id.s.clone()).collect())
+        .unwrap_or_default();
+
+    let status = PostStatus::parse(entry.get("status").and_then(|val| val.s.as_deref()));
+    let published = entry
+        .get("published")
+        .and_then(|val| val.n.as_ref())
+        .and_then(|published| published.parse().ok());
+    let kind = PostKind::parse_stored(entry.get("kind").and_then(|val| val.s.as_deref()), text);
+    let string = |name: &str| entry.get(name).and_then(|val| val.s.clone());
 
     Post {
         id: String::from(entry.get("id").unwrap().s.as_ref().unwrap()),
         text: String::from(text),
         images,
         tg_id,
+        continuation_ids,
+        status,
+        kind,
+        published,
+        source: string("source").unwrap_or_default(),
+        permalink: string("permalink"),
+        author: string("author"),
+        truncated: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(value: &str) -> AttributeValue {
+        AttributeValue {
+            s: Some(String::from(value)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn build_post_from_lists() {
+        let mut entry = HashMap::new();
+        entry.insert(String::from("id"), string("1"));
+        entry.insert(
+            String::from("images"),
+            AttributeValue {
+                l: Some(vec![string("url-1"), string("url-2")]),
+                ..Default::default()
+            },
+        );
+        entry.insert(
+            String::from("image_ids"),
+            AttributeValue {
+                l: Some(vec![
+                    string("11"),
+                    AttributeValue {
+                        null: Some(true),
+                        ..Default::default()
+                    },
+                ]),
+                ..Default::default()
+            },
+        );
+        entry.insert(
+            String::from("image_thumbnails"),
+            AttributeValue {
+                l: Some(vec![
+                    AttributeValue {
+                        null: Some(true),
+                        ..Default::default()
+                    },
+                    string("thumbnail-2"),
+                ]),
+                ..Default::default()
+            },
+        );
+
+        let post = build_post(entry);
+        assert_eq!(
+            post.images,
+            vec![
+                Image {
+                    url: String::from("url-1"),
+                    thumbnail_url: None,
+                    key: String::from("url-1"),
+                    tg_id: Some(String::from("11")),
+                },
+                Image {
+                    url: String::from("url-2"),
+                    thumbnail_url: Some(String::from("thumbnail-2")),
+                    key: String::from("url-2"),
+                    tg_id: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn build_post_from_string_sets() {
+        let mut entry = HashMap::new();
+        entry.insert(String::from("id"), string("1"));
+        entry.insert(String::from("text"), string("text"));
+        entry.insert(String::from("message_id"), string("10"));
+        entry.insert(
+            String::from("images"),
+            AttributeValue {
+                ss: Some(vec![String::from("url-1")]),
+                ..Default::default()
+            },
+        );
+        entry.insert(
+            String::from("image_ids"),
+            AttributeValue {
+                ss: Some(vec![String::from("11")]),
+                ..Default::default()
+            },
+        );
+
+        let post = build_post(entry);
+        assert_eq!(post.text, "text");
+        assert_eq!(post.tg_id, Some(String::from("10")));
+        assert_eq!(post.status, PostStatus::Sent);
+        assert_eq!(
+            post.images,
+            vec![Image {
+                url: String::from("url-1"),
+                thumbnail_url: None,
+                key: String::from("url-1"),
+                tg_id: Some(String::from("11")),
+            }]
+        );
+    }
+
+    #[test]
+    fn build_post_with_continuations() {
+        let mut entry = HashMap::new();
+        entry.insert(String::from("id"), string("1"));
+        entry.insert(String::from("message_id"), string("10"));
+        entry.insert(
+            String::from("continuation_ids"),
+            AttributeValue {
+                l: Some(vec![string("11"), string("12")]),
+                ..Default::default()
+            },
+        );
+
+        let post = build_post(entry);
+        assert_eq!(post.tg_id, Some(String::from("10")));
+        assert_eq!(post.continuation_ids, vec!["11", "12"]);
+    }
+
+    #[test]
+    fn build_sending_announcement() {
+        let mut entry = HashMap::new();
+        entry.insert(String::from("id"), string("1"));
+        entry.insert(String::from("status"), string("sending"));
+        entry.insert(String::from("kind"), string("announcement"));
+
+        let post = build_post(entry);
+        assert_eq!(post.status, PostStatus::Sending);
+        assert_eq!(post.kind, PostKind::Announcement);
+    }
+
+    #[test]
+    fn build_post_with_details() {
+        let mut entry = HashMap::new();
+        entry.insert(String::from("id"), string("facebook:1"));
+        entry.insert(String::from("source"), string("facebook"));
+        entry.insert(
+            String::from("permalink"),
+            string("https://www.facebook.com/kantineKliversala/posts/1"),
+        );
+        entry.insert(String::from("author"), string("Kantīne \"Klīversala\""));
+
+        let post = build_post(entry);
+        assert_eq!(post.source, "facebook");
+        assert_eq!(
+            post.permalink,
+            Some(String::from(
+                "https://www.facebook.com/kantineKliversala/posts/1"
+            ))
+        );
+        assert_eq!(post.author, Some(String::from("Kantīne \"Klīversala\"")));
+    }
+
+    #[test]
+    fn build_post_without_details() {
+        let mut entry = HashMap::new();
+        entry.insert(String::from("id"), string("1"));
+
+        let post = build_post(entry);
+        assert_eq!(post.source, "");
+        assert_eq!(post.permalink, None);
+        assert_eq!(post.author, None);
     }
 }
@@ -0,0 +1,85 @@
+use std::error::Error as StdError;
+use std::fmt;
+
+use reqwest::Error as ReqwestError;
+use rusoto_core::RusotoError;
+use rusqlite::Error as SqliteError;
+
+use crate::telegram::error::TelegramError;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The source could not be reached.
+    SourceFetch(ReqwestError),
+    /// The source answered with an unsuccessful status.
+    SourceStatus { status: u16, body: String },
+    /// The source answered with content that could not be understood.
+    SourceParse(String),
+    /// Reading from or writing to the post store failed.
+    Store(Box<dyn StdError + Send + Sync>),
+    /// A Telegram API call failed.
+    Telegram(TelegramError),
+}
+
+impl Error {
+    /// Whether the failure is likely transient, so retrying the same operation later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::SourceFetch(_) => true,
+            Error::SourceStatus { status, .. } => *status == 429 || *status >= 500,
+            Error::SourceParse(_) => false,
+            Error::Store(_) => true,
+            Error::Telegram(err) => err.is_retryable(),
+        }
+    }
+}
+
+impl From<ReqwestError> for Error {
+    fn from(error: ReqwestError) -> Self {
+        Error::SourceFetch(error)
+    }
+}
+
+impl From<TelegramError> for Error {
+    fn from(error: TelegramError) -> Self {
+        Error::Telegram(error)
+    }
+}
+
+impl<E: StdError + Send + Sync + 'static> From<RusotoError<E>> for Error {
+    fn from(error: RusotoError<E>) -> Self {
+        Error::Store(Box::new(error))
+    }
+}
+
+impl From<SqliteError> for Error {
+    fn from(error: SqliteError) -> Self {
+        Error::Store(Box::new(error))
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::SourceFetch(err) => Some(err),
+            Error::SourceStatus { .. } => None,
+            Error::SourceParse(_) => None,
+            Error::Store(err) => Some(err.as_ref()),
+            Error::Telegram(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::SourceFetch(err) => write!(f, "failed to fetch posts: {}", err),
+            Error::SourceStatus { status, body } => {
+                write!(f, "source responded with {}: {}", status, body)
+            }
+            Error::SourceParse(err) => write!(f, "failed to parse posts: {}", err),
+            Error::Store(err) => write!(f, "post store error: {}", err),
+            Error::Telegram(err) => write!(f, "telegram error: {}", err),
+        }
+    }
+}
@@ -0,0 +1,335 @@
+/// Markup that post text is rendered into before it is sent to Telegram.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseMode {
+    Plain,
+    Html,
+    MarkdownV2,
+}
+
+impl ParseMode {
+    /// The `parse_mode` to send along with the text, if any.
+    pub fn as_str(self) -> Option<&'static str> {
+        match self {
+            ParseMode::Plain => None,
+            ParseMode::Html => Some("HTML"),
+            ParseMode::MarkdownV2 => Some("MarkdownV2"),
+        }
+    }
+}
+
+// Headings of a menu, like "Lielais pusdienu piedāvājums:", are short lines ending with a colon.
+const HEADING_LIMIT: usize = 60;
+
+/// Renders post text, as converted from HTML by `html2md`, into Telegram markup.
+///
+/// Markdown escapes and hard line break spaces are dropped, `**strong**` text and headings are
+/// made bold, and every character the markup reserves is escaped.
+pub fn render(text: &str, mode: ParseMode) -> String {
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        let line = unescape(line.trim());
+        // Keep paragraphs apart by a single empty line.
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(render_line(&line, mode));
+    }
+    while lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+fn render_line(line: &str, mode: ParseMode) -> String {
+    if is_heading(line) {
+        return bold(&escape(&line.replace("**", ""), mode), mode);
+    }
+    let parts: Vec<&str> = line.split("**").collect();
+    // An unpaired marker is not markup.
+    if parts.len().is_multiple_of(2) {
+        return escape(line, mode);
+    }
+    parts
+        .iter()
+        .enumerate()
+        .map(|(index, part)| {
+            let part = escape(part, mode);
+            if index % 2 == 1 && !part.is_empty() {
+                bold(&part, mode)
+            } else {
+                part
+            }
+        })
+        .collect()
+}
+
+fn is_heading(line: &str) -> bool {
+    line.ends_with(':') && line.chars().count() <= HEADING_LIMIT
+}
+
+fn bold(text: &str, mode: ParseMode) -> String {
+    match mode {
+        ParseMode::Plain => String::from(text),
+        ParseMode::Html => format!("<b>{}</b>", text),
+        ParseMode::MarkdownV2 => format!("*{}*", text),
+    }
+}
+
+fn escape(text: &str, mode: ParseMode) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match (mode, c) {
+            (ParseMode::Html, '&') => escaped.push_str("&amp;"),
+            (ParseMode::Html, '<') => escaped.push_str("&lt;"),
+            (ParseMode::Html, '>') => escaped.push_str("&gt;"),
+            (ParseMode::MarkdownV2, '_')
+            | (ParseMode::MarkdownV2, '*')
+            | (ParseMode::MarkdownV2, '[')
+            | (ParseMode::MarkdownV2, ']')
+            | (ParseMode::MarkdownV2, '(')
+            | (ParseMode::MarkdownV2, ')')
+            | (ParseMode::MarkdownV2, '~')
+            | (ParseMode::MarkdownV2, '`')
+            | (ParseMode::MarkdownV2, '>')
+            | (ParseMode::MarkdownV2, '#')
+            | (ParseMode::MarkdownV2, '+')
+            | (ParseMode::MarkdownV2, '-')
+            | (ParseMode::MarkdownV2, '=')
+            | (ParseMode::MarkdownV2, '|')
+            | (ParseMode::MarkdownV2, '{')
+            | (ParseMode::MarkdownV2, '}')
+            | (ParseMode::MarkdownV2, '.')
+            | (ParseMode::MarkdownV2, '!')
+            | (ParseMode::MarkdownV2, '\\') => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Drops the backslashes `html2md` puts in front of punctuation that means something in Markdown.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek().is_some_and(|next| next.is_ascii_punctuation()) {
+            continue;
+        }
+        unescaped.push(c);
+    }
+    unescaped
+}
+
+/// Length of text as Telegram counts it, in UTF-16 code units, so most emoji count as two.
+pub fn length(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Splits text longer than `limit` into parts that fit, breaking it between paragraphs if
+/// possible, otherwise between lines, then words, and only as a last resort inside a word. Parts
+/// never end in the middle of a character.
+pub fn split(text: &str, limit: usize) -> Vec<String> {
+    let mut parts = vec![];
+    let mut rest = text;
+    while length(rest) > limit {
+        let end = break_point(rest, limit);
+        let part = rest[..end].trim_end();
+        if !part.is_empty() {
+            parts.push(String::from(part));
+        }
+        rest = rest[end..].trim_start();
+    }
+    if !rest.is_empty() {
+        parts.push(String::from(rest));
+    }
+    parts
+}
+
+// Byte index at which to end a part of `text` no longer than `limit`.
+fn break_point(text: &str, limit: usize) -> usize {
+    let mut fits = 0;
+    let mut fits_length = 0;
+    for (index, c) in text.char_indices() {
+        fits_length += c.len_utf16();
+        if fits_length > limit {
+            break;
+        }
+        fits = index + c.len_utf8();
+    }
+    // A single character always makes progress, even with an unreasonably small limit.
+    if fits == 0 {
+        return text.chars().next().map_or(text.len(), char::len_utf8);
+    }
+    for separator in &["\n\n", "\n", " "] {
+        if text[fits..].starts_with(separator) {
+            return fits;
+        }
+        match text[..fits].rfind(separator) {
+            Some(index) if !text[..index].trim().is_empty() => return index,
+            _ => {}
+        }
+    }
+    fits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MENU: &str = "Pusdienu piedāvājums 7. februārī.  \n Dienas piedāvājums pieejams 11:00-16:00\n\n Mazais pusdienu piedāvājums:  \n🍗v/g saldakābā mērcē vai 🥘makaroni \"Jūrnieku gaumē\", vai 🌽kuskuss ar dārzeņiem  \n💸 3,90€\n\n Lielais pusdienu piedāvājums:  \n🍲frikadeļu zupa vai dārzeņu krēmzupa, vai 🍰 dienas deserts  \n💸 4,60€\n\n Labu apetīti!";
+
+    #[test]
+    fn renders_menu_as_html() {
+        assert_eq!(
+            render(MENU, ParseMode::Html),
+            "Pusdienu piedāvājums 7. februārī.\n\
+             Dienas piedāvājums pieejams 11:00-16:00\n\
+             \n\
+             <b>Mazais pusdienu piedāvājums:</b>\n\
+             🍗v/g saldakābā mērcē vai 🥘makaroni \"Jūrnieku gaumē\", vai 🌽kuskuss ar dārzeņiem\n\
+             💸 3,90€\n\
+             \n\
+             <b>Lielais pusdienu piedāvājums:</b>\n\
+             🍲frikadeļu zupa vai dārzeņu krēmzupa, vai 🍰 dienas deserts\n\
+             💸 4,60€\n\
+             \n\
+             Labu apetīti!"
+        );
+    }
+
+    #[test]
+    fn renders_menu_as_markdown() {
+        assert_eq!(
+            render(MENU, ParseMode::MarkdownV2),
+            "Pusdienu piedāvājums 7\\. februārī\\.\n\
+             Dienas piedāvājums pieejams 11:00\\-16:00\n\
+             \n\
+             *Mazais pusdienu piedāvājums:*\n\
+             🍗v/g saldakābā mērcē vai 🥘makaroni \"Jūrnieku gaumē\", vai 🌽kuskuss ar dārzeņiem\n\
+             💸 3,90€\n\
+             \n\
+             *Lielais pusdienu piedāvājums:*\n\
+             🍲frikadeļu zupa vai dārzeņu krēmzupa, vai 🍰 dienas deserts\n\
+             💸 4,60€\n\
+             \n\
+             Labu apetīti\\!"
+        );
+    }
+
+    #[test]
+    fn renders_plain_text_without_markup() {
+        assert_eq!(
+            render(
+                "Mazais pusdienu piedāvājums:  \n\\*akcija\\* <3",
+                ParseMode::Plain
+            ),
+            "Mazais pusdienu piedāvājums:\n*akcija* <3"
+        );
+    }
+
+    #[test]
+    fn escapes_reserved_characters() {
+        let text = r"_*[]()~`>#+-=|{}.!\ & <b>";
+        assert_eq!(
+            render(text, ParseMode::MarkdownV2),
+            r"\_\*\[\]\(\)\~\`\>\#\+\-\=\|\{\}\.\!\\ & <b\>"
+        );
+        assert_eq!(
+            render(text, ParseMode::Html),
+            r"_*[]()~`&gt;#+-=|{}.!\ &amp; &lt;b&gt;"
+        );
+    }
+
+    #[test]
+    fn drops_markdown_escapes() {
+        assert_eq!(
+            render(r"11:00\-16:00 \*\*\* \_a\_", ParseMode::Html),
+            "11:00-16:00 *** _a_"
+        );
+    }
+
+    #[test]
+    fn renders_strong_text_as_bold() {
+        assert_eq!(
+            render("**Svarīgi!** šodien slēgts", ParseMode::Html),
+            "<b>Svarīgi!</b> šodien slēgts"
+        );
+        assert_eq!(
+            render("**Svarīgi!** šodien slēgts", ParseMode::MarkdownV2),
+            "*Svarīgi\\!* šodien slēgts"
+        );
+        assert_eq!(render("2 ** 3", ParseMode::Html), "2 ** 3");
+    }
+
+    #[test]
+    fn collapses_empty_lines() {
+        assert_eq!(
+            render(
+                "!!!! slēgts !!!!\n\n\n\n Mazais pusdienu piedāvājums:\n\n",
+                ParseMode::Plain
+            ),
+            "!!!! slēgts !!!!\n\nMazais pusdienu piedāvājums:"
+        );
+    }
+
+    #[test]
+    fn long_line_ending_with_colon_is_not_a_heading() {
+        let line = "Šodien Klīversalā gaidām visus ar īpašu piedāvājumu, kas sastāv no:";
+        assert_eq!(render(line, ParseMode::Html), line);
+    }
+
+    #[test]
+    fn counts_emoji_as_telegram_does() {
+        assert_eq!(length("Labu apetīti!"), 13);
+        assert_eq!(length("💸 3,90€"), 8);
+    }
+
+    #[test]
+    fn short_text_is_not_split() {
+        assert_eq!(split(MENU, 4096), vec![MENU]);
+        assert_eq!(split("", 4096), Vec::<String>::new());
+    }
+
+    #[test]
+    fn splits_between_paragraphs() {
+        assert_eq!(
+            split("Pirmdiena\nzupa\n\nOtrdiena\nsalāti", 20),
+            vec!["Pirmdiena\nzupa", "Otrdiena\nsalāti"]
+        );
+    }
+
+    #[test]
+    fn splits_between_lines_of_a_long_paragraph() {
+        assert_eq!(
+            split("Pirmdiena: zupa\nOtrdiena: salāti\nTrešdiena: deserts", 35),
+            vec!["Pirmdiena: zupa\nOtrdiena: salāti", "Trešdiena: deserts"]
+        );
+    }
+
+    #[test]
+    fn splits_between_words_of_a_long_line() {
+        assert_eq!(
+            split("frikadeļu zupa vai dārzeņu krēmzupa", 20),
+            vec!["frikadeļu zupa vai", "dārzeņu krēmzupa"]
+        );
+    }
+
+    #[test]
+    fn splits_long_words_between_characters() {
+        assert_eq!(split("🍗🍗🍗🍗🍗", 4), vec!["🍗🍗", "🍗🍗", "🍗"]);
+        assert_eq!(split("ēēēēē", 2), vec!["ēē", "ēē", "ē"]);
+    }
+
+    #[test]
+    fn parts_fit_the_limit() {
+        let text = MENU.repeat(40);
+        let parts = split(&text, 4096);
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(length(part) <= 4096);
+        }
+    }
+}
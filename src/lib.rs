@@ -1,103 +1,2993 @@
 #[macro_use]
 extern crate lazy_static;
 
+use std::collections::HashSet;
 use std::env;
-use std::error::Error;
+use std::fmt;
+use std::ptr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use log::{error, info};
+use log::{error, info, warn};
+
+pub use error::Error;
 
 use dynamo_db::DynamoClient;
+use publisher::{Publisher, Router, Routes};
 use sources::facebook::FacebookSource;
-use sources::PostSource;
+use sources::graph::GraphSource;
+use sources::{Image, Post, PostKind, PostSource, PostStatus};
+use sqlite_db::SqliteClient;
+use store::PostStore;
 use telegram::client::TelegramClient;
+use telegram::config::ClientConfig;
+use telegram::error::TelegramError;
 
 pub mod dynamo_db;
+pub mod error;
+pub mod formatter;
+pub mod memory_db;
+pub mod menu;
+pub mod publisher;
 pub mod sources;
+pub mod sqlite_db;
+pub mod store;
 pub mod telegram;
 
-pub async fn process_posts() -> Result<(), Box<dyn Error>> {
+/// What to do with channel messages of posts that have been deleted from the source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeletedPosts {
+    Delete,
+    Withdraw,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub deleted_posts: DeletedPosts,
+    /// New posts published longer ago than this are recorded without sending them, so that a
+    /// fresh or lost store does not flood the channel with old menus.
+    pub max_age: Option<Duration>,
+    /// Records every new post without sending it, to start from what is already on the page.
+    pub seed: bool,
+    /// Most posts removed from the channel per source in a single run, the rest are removed on
+    /// the following runs.
+    pub max_deletions: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            deleted_posts: DeletedPosts::Delete,
+            max_age: None,
+            seed: false,
+            max_deletions: 5,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Config {
+        let mut config = Config::default();
+        if let Ok(deleted_posts) = env::var("DELETED_POSTS") {
+            config.deleted_posts = match deleted_posts.as_str() {
+                "delete" => DeletedPosts::Delete,
+                "withdraw" => DeletedPosts::Withdraw,
+                _ => panic!("Invalid DELETED_POSTS env var: {}", deleted_posts),
+            };
+        }
+        if let Ok(max_age) = env::var("MAX_POST_AGE_HOURS") {
+            let hours: u64 = max_age
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid MAX_POST_AGE_HOURS env var: {}", max_age));
+            config.max_age = Some(Duration::from_secs(hours * 60 * 60));
+        }
+        if let Ok(seed) = env::var("SEED_MODE") {
+            config.seed = seed
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid SEED_MODE env var: {}", seed));
+        }
+        if let Ok(max_deletions) = env::var("MAX_DELETIONS_PER_RUN") {
+            config.max_deletions = max_deletions.parse().unwrap_or_else(|_| {
+                panic!("Invalid MAX_DELETIONS_PER_RUN env var: {}", max_deletions)
+            });
+        }
+        config
+    }
+}
+
+pub async fn process_posts() -> Result<RunSummary, Error> {
     let token = env::var("TG_TOKEN").expect("Missing TG_TOKEN env var");
-    let chat_id = env::var("TG_CHAT_ID").expect("Missing TG_CHAT_ID env var");
-    let table_name = env::var("TABLE_NAME").expect("Missing TABLE_NAME env var");
+    let config = Config::from_env();
+
+    let client_config = ClientConfig::from_env();
+    let routes = match env::var("TG_ROUTES") {
+        Ok(routes) => parse_routes(&routes)
+            .unwrap_or_else(|| panic!("Invalid TG_ROUTES env var: {}", routes))
+            .into_iter()
+            .fold(Routes::new(), |routes, (kinds, chat_id)| {
+                let client = TelegramClient::new(token.clone(), chat_id);
+                routes.with(&kinds, client.with_config(client_config.clone()))
+            }),
+        Err(_) => {
+            let chat_id = env::var("TG_CHAT_ID").expect("Missing TG_CHAT_ID env var");
+            let client = TelegramClient::new(token, chat_id);
+            Routes::new().with(&PostKind::ALL, client.with_config(client_config))
+        }
+    };
+    let post_source: Box<dyn PostSource> = match env::var("FB_ACCESS_TOKEN") {
+        Ok(access_token) => {
+            let page_id =
+                env::var("FB_PAGE_ID").unwrap_or_else(|_| String::from("kantineKliversala"));
+            Box::new(GraphSource::new(page_id, access_token))
+        }
+        Err(_) => Box::new(FacebookSource::new(
+            "https://www.facebook.com/pg/kantineKliversala/posts/",
+        )),
+    };
+    let post_sources = vec![post_source];
+
+    if let Ok(database_path) = env::var("DATABASE_PATH") {
+        let sqlite_client = SqliteClient::new(&database_path)?;
+        return process_posts_with(&post_sources, &sqlite_client, &routes, &config).await;
+    }
 
+    let table_name = env::var("TABLE_NAME").expect("Missing TABLE_NAME env var");
     let dynamo_client = DynamoClient::new(table_name);
-    let telegram_client = TelegramClient::new(token, chat_id);
-    let post_sources = [FacebookSource::new(
-        "https://www.facebook.com/pg/kantineKliversala/posts/",
-    )];
-    process_posts_with(&post_sources, &dynamo_client, &telegram_client).await
+    process_posts_with(&post_sources, &dynamo_client, &routes, &config).await
+}
+
+// Parses routes like "daily_menu,weekly_menu=@kliversala;announcement=-1001234567890", which
+// forward the listed kinds of posts to the chat after "=". Kinds that are not listed are not
+// forwarded anywhere.
+fn parse_routes(routes: &str) -> Option<Vec<(Vec<PostKind>, String)>> {
+    routes
+        .split(';')
+        .filter(|route| !route.trim().is_empty())
+        .map(|route| {
+            let mut parts = route.splitn(2, '=');
+            let kinds = parts
+                .next()?
+                .split(',')
+                .map(|kind| PostKind::parse(kind.trim()))
+                .collect::<Option<Vec<PostKind>>>()?;
+            let chat_id = parts.next()?.trim();
+            if chat_id.is_empty() {
+                return None;
+            }
+            Some((kinds, String::from(chat_id)))
+        })
+        .collect()
+}
+
+// Telegram albums hold between 2 and 10 photos, captions are limited to 1024 characters and
+// messages to 4096.
+const ALBUM_LIMIT: usize = 10;
+const CAPTION_LIMIT: usize = 1024;
+const MESSAGE_LIMIT: usize = 4096;
+
+const WITHDRAWN_NOTICE: &str = "❌ Ieraksts ir atsaukts.";
+
+// Sources only show their recent posts, so stored posts published longer ago are not read at the
+// start of a run. They are neither known to their source nor checked for deletion.
+const KNOWN_PERIOD: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// What a single run of `process_posts_with` did.
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    pub sent: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub skipped: usize,
+    pub failures: Vec<Failure>,
+}
+
+/// A post, or a whole source when `post_id` is `None`, that could not be processed.
+///
+/// Whatever was sent before the failure is stored, the rest is retried on the next run.
+#[derive(Debug)]
+pub struct Failure {
+    pub post_id: Option<String>,
+    pub error: Error,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} sent, {} updated, {} removed, {} skipped, {} failed",
+            self.sent,
+            self.updated,
+            self.removed,
+            self.skipped,
+            self.failures.len()
+        )
+    }
+}
+
+enum Outcome {
+    Sent,
+    Updated,
+    Skipped,
+    Unchanged,
 }
 
-async fn process_posts_with<T: PostSource>(
+/// Processes every post in isolation, so that a failing post does not hold back the others.
+///
+/// When the bot loses access to a chat, posts routed to it are skipped for the rest of the run.
+/// Only once no chat is left does the run stop, returning the error.
+///
+/// Each post is forwarded through the publisher `router` picks for its kind, posts of kinds
+/// without a route are left alone.
+///
+/// Posts are stored under their id prefixed with the name of their source, so that sources of
+/// different types, boxed as `dyn PostSource`, can share a store.
+pub async fn process_posts_with<T: PostSource, S: PostStore, R: Router>(
     post_sources: &[T],
-    dynamo_client: &DynamoClient,
-    telegram_client: &TelegramClient,
-) -> Result<(), Box<dyn Error>> {
+    post_store: &S,
+    router: &R,
+    config: &Config,
+) -> Result<RunSummary, Error> {
+    let mut summary = RunSummary::default();
+    let mut disabled = HashSet::new();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let stored = post_store
+        .scan_posts(now.saturating_sub(KNOWN_PERIOD).as_secs())
+        .await?;
     for post_source in post_sources {
-        let posts = post_source.fetch_posts().await?;
-        info!("found {} posts", posts.len());
-
-        for mut post in posts {
-            match dynamo_client.get_post(&post.id).await? {
-                None => {
-                    info!("sending notification for post: {:?}", post);
-                    if &post.text != "" {
-                        let message_id = telegram_client.send_message(&post.text).await?;
-                        post.tg_id = Some(message_id);
+        let result = process_source(
+            post_source,
+            post_store,
+            router,
+            config,
+            &stored,
+            &mut disabled,
+            &mut summary,
+        )
+        .await;
+        match result {
+            Err(error) if is_chat_unavailable(&error) => return Err(error),
+            Err(error) => {
+                error!("Failed to process source {}: {}", post_source.name(), error);
+                summary.failures.push(Failure {
+                    post_id: None,
+                    error,
+                });
+            }
+            Ok(()) => {}
+        }
+    }
+    Ok(summary)
+}
+
+async fn process_source<T: PostSource, S: PostStore, R: Router>(
+    post_source: &T,
+    post_store: &S,
+    router: &R,
+    config: &Config,
+    stored: &[Post],
+    disabled: &mut HashSet<PostKind>,
+    summary: &mut RunSummary,
+) -> Result<(), Error> {
+    let name = post_source.name();
+    let stored: Vec<Post> = stored
+        .iter()
+        .filter(|stored| source_of(&stored.id) == Some(name))
+        .cloned()
+        .collect();
+    let known: Vec<Post> = stored
+        .iter()
+        .map(|stored| Post {
+            id: String::from(source_id(&stored.id)),
+            ..stored.clone()
+        })
+        .collect();
+    let mut posts = post_source.fetch_posts(&known).await?;
+    info!("found {} posts from {}", posts.len(), name);
+    for post in &mut posts {
+        post.id = storage_key(name, &post.id);
+    }
+    let deleted_posts = find_deleted_posts(stored, &posts);
+
+    for mut post in posts {
+        let post_id = post.id.clone();
+        let result = match find_stored(post_store, &mut post).await {
+            Ok(_) if disabled.contains(&post.kind) => {
+                info!("chat of the post is unavailable, skipping it: {}", &post_id);
+                continue;
+            }
+            Ok(stored) => {
+                let kind = post.kind;
+                process_post(post_store, router, config, post, stored)
+                    .await
+                    .map_err(|error| (kind, error))
+            }
+            Err(error) => Err((post.kind, error)),
+        };
+        match result {
+            Ok(Outcome::Sent) => summary.sent += 1,
+            Ok(Outcome::Updated) => summary.updated += 1,
+            Ok(Outcome::Skipped) => summary.skipped += 1,
+            Ok(Outcome::Unchanged) => {}
+            Err((kind, error))
+                if is_chat_unavailable(&error) && !disable_route(router, kind, disabled) =>
+            {
+                return Err(error)
+            }
+            Err((_, error)) => {
+                error!("Failed to process post {}: {}", post_id, error);
+                summary.failures.push(Failure {
+                    post_id: Some(post_id),
+                    error,
+                });
+            }
+        }
+    }
+
+    // A post missing from the fetched list may just have been skipped by the source, so it is
+    // only removed once the source confirms it is gone.
+    let mut removed = 0;
+    for deleted_post in deleted_posts {
+        if removed == config.max_deletions {
+            warn!(
+                "more than {} posts of {} are missing, leaving the rest for the next run",
+                config.max_deletions, name
+            );
+            break;
+        }
+        if disabled.contains(&deleted_post.kind) {
+            continue;
+        }
+        match post_source.is_deleted(&deleted_post).await {
+            Ok(true) => removed += 1,
+            Ok(false) => {
+                warn!(
+                    "post {} is missing from {} but has not been deleted",
+                    &deleted_post.id, name
+                );
+                continue;
+            }
+            Err(error) => {
+                error!("Failed to check post {}: {}", deleted_post.id, error);
+                summary.failures.push(Failure {
+                    post_id: Some(deleted_post.id),
+                    error,
+                });
+                continue;
+            }
+        }
+        info!("post has been deleted: {}", &deleted_post.id);
+        // Without a route for its kind any more, the post is just forgotten.
+        let result = match router.route(deleted_post.kind) {
+            Some(publisher) => remove_post(publisher, &deleted_post, config.deleted_posts).await,
+            None => Ok(()),
+        };
+        let result = match result {
+            Ok(()) => post_store.delete_post(&deleted_post.id).await,
+            Err(error) => Err(error),
+        };
+        match result {
+            Ok(()) => summary.removed += 1,
+            Err(error)
+                if is_chat_unavailable(&error)
+                    && !disable_route(router, deleted_post.kind, disabled) =>
+            {
+                return Err(error)
+            }
+            Err(error) => {
+                error!("Failed to remove post {}: {}", deleted_post.id, error);
+                summary.failures.push(Failure {
+                    post_id: Some(deleted_post.id),
+                    error,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// Stores whatever made it to the channel even when sending or updating the post failed halfway,
+// so that the next run only retries what is still missing instead of sending it all again.
+async fn process_post<S: PostStore, R: Router>(
+    post_store: &S,
+    router: &R,
+    config: &Config,
+    mut post: Post,
+    stored: Option<Post>,
+) -> Result<Outcome, Error> {
+    let publisher = match router.route(post.kind) {
+        Some(publisher) => publisher,
+        None => {
+            info!(
+                "posts of kind {} are not forwarded: {}",
+                post.kind.as_str(),
+                &post.id
+            );
+            return Ok(Outcome::Unchanged);
+        }
+    };
+
+    let mut sent_post = match stored {
+        Some(sent_post) => sent_post,
+        None if config.seed || is_stale(&post, config.max_age) => {
+            info!("recording post without sending it: {}", &post.id);
+            post.status = PostStatus::Skipped;
+            post_store.put_post(&post).await?;
+            return Ok(Outcome::Skipped);
+        }
+        None if post.truncated => return Ok(leave_truncated(&post)),
+        None => return send_post(post_store, publisher, post).await,
+    };
+    if sent_post.status == PostStatus::Skipped {
+        return Ok(Outcome::Unchanged);
+    }
+    if post.truncated {
+        return Ok(leave_truncated(&post));
+    }
+    let stored = sent_post.clone();
+    copy_details(&post, &mut sent_post);
+    let detailed = sent_post.clone();
+
+    // The previous run stopped while sending the post, after the last recorded message id.
+    let interrupted = sent_post.status == PostStatus::Sending;
+    if interrupted {
+        warn!(
+            "sending post {} was interrupted, sending what is missing",
+            &post.id
+        );
+        if !is_sent(&sent_post) {
+            return send_post(post_store, publisher, post).await;
+        }
+        sent_post.status = PostStatus::Sent;
+    } else {
+        info!("post is already sent: {}", &sent_post.id);
+    }
+
+    let result = update_post(publisher, &mut sent_post, &post).await;
+    if sent_post == stored {
+        return result.map(|()| Outcome::Unchanged);
+    }
+    post_store.put_post(&sent_post).await?;
+    result.map(|()| {
+        if interrupted {
+            Outcome::Sent
+        } else if sent_post == detailed {
+            Outcome::Unchanged
+        } else {
+            Outcome::Updated
+        }
+    })
+}
+
+// Keeps what the source tells about a post up to date without touching its messages, which also
+// fills in the details of posts stored before they were recorded.
+fn copy_details(post: &Post, sent_post: &mut Post) {
+    sent_post.source = post.source.clone();
+    sent_post.permalink = post.permalink.clone();
+    sent_post.author = post.author.clone();
+    if post.published.is_some() {
+        sent_post.published = post.published;
+    }
+}
+
+// Sends a new post in two phases: the post is stored as `Sending` before anything is published
+// and every message id is stored as soon as it arrives, so that a run killed halfway leaves a
+// record of what already reached the channel.
+async fn send_post<S: PostStore, P: Publisher>(
+    post_store: &S,
+    publisher: &P,
+    mut post: Post,
+) -> Result<Outcome, Error> {
+    info!("sending notification for post: {:?}", post);
+    post.status = PostStatus::Sending;
+    post_store.put_post(&post).await?;
+
+    let result = publish_post(post_store, publisher, &mut post).await;
+    if result.is_err() && !is_sent(&post) {
+        post_store.delete_post(&post.id).await?;
+        return result.map(|()| Outcome::Sent);
+    }
+    post.status = PostStatus::Sent;
+    post_store.put_post(&post).await?;
+    result.map(|()| Outcome::Sent)
+}
+
+// A source only returns its most recent posts, so a stored post counts as deleted only if it is
+// missing from the fetched list while being newer than the oldest fetched one.
+fn find_deleted_posts(stored: Vec<Post>, posts: &[Post]) -> Vec<Post> {
+    let oldest = match posts.iter().filter_map(|post| post_number(&post.id)).min() {
+        Some(oldest) => oldest,
+        None => return vec![],
+    };
+    stored
+        .into_iter()
+        .filter(|stored| post_number(&stored.id).is_some_and(|number| number >= oldest))
+        .filter(|stored| posts.iter().all(|post| post.id != stored.id))
+        .collect()
+}
+
+// Posts without a timestamp are never stale, there is no telling how old they are.
+fn is_stale(post: &Post, max_age: Option<Duration>) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    match (post.published, max_age) {
+        (Some(published), Some(max_age)) => Duration::from_secs(published) + max_age < now,
+        _ => false,
+    }
+}
+
+fn post_number(key: &str) -> Option<u64> {
+    source_id(key).parse().ok()
+}
+
+fn storage_key(source: &str, id: &str) -> String {
+    format!("{}:{}", source, id)
+}
+
+fn source_of(key: &str) -> Option<&str> {
+    key.find(':').map(|end| &key[..end])
+}
+
+// The id the source gave a post stored under `key`.
+fn source_id(key: &str) -> &str {
+    match key.find(':') {
+        Some(end) => &key[end + 1..],
+        None => key,
+    }
+}
+
+// Posts stored before their keys were prefixed with the source name are moved under the new key
+// the first time they are fetched again.
+async fn take_unprefixed_post<S: PostStore>(
+    post_store: &S,
+    key: &str,
+) -> Result<Option<Post>, Error> {
+    let id = match key.find(':') {
+        Some(end) => &key[end + 1..],
+        None => return Ok(None),
+    };
+    let mut post = match post_store.get_post(id).await? {
+        Some(post) => post,
+        None => return Ok(None),
+    };
+    info!("moving post {} to {}", id, key);
+    post.id = String::from(key);
+    post_store.put_post(&post).await?;
+    post_store.delete_post(id).await?;
+    Ok(Some(post))
+}
+
+async fn remove_post<P: Publisher>(
+    publisher: &P,
+    post: &Post,
+    policy: DeletedPosts,
+) -> Result<(), Error> {
+    let captioned = has_caption(post);
+    if let (DeletedPosts::Withdraw, Some(message_id)) = (policy, &post.tg_id) {
+        let text = format!("{}\n\n{}", WITHDRAWN_NOTICE, post.text);
+        let fits = if captioned {
+            fits_caption(&text)
+        } else {
+            fits_message(&text)
+        };
+        let text = if fits {
+            text.as_str()
+        } else {
+            WITHDRAWN_NOTICE
+        };
+        let result = if captioned {
+            publisher.edit_caption(message_id, text).await
+        } else {
+            publisher.edit_text(message_id, text).await
+        };
+        return ignore_missing(edited(result));
+    }
+
+    // Posts without text have nothing to attach the notice to, so they are always deleted.
+    let message_ids = post
+        .tg_id
+        .iter()
+        .filter(|_| !captioned)
+        .chain(&post.continuation_ids)
+        .chain(post.images.iter().filter_map(|image| image.tg_id.as_ref()));
+    for message_id in message_ids {
+        ignore_missing(publisher.delete(message_id).await)?;
+    }
+    Ok(())
+}
+
+// Stores every message id as soon as it is known, so that the stored post reflects what has
+// been sent even when a later message fails or the run is killed.
+async fn publish_post<S: PostStore, P: Publisher>(
+    post_store: &S,
+    publisher: &P,
+    post: &mut Post,
+) -> Result<(), Error> {
+    if post.images.len() > 1 && fits_caption(&post.text) {
+        for start in (0..post.images.len()).step_by(ALBUM_LIMIT) {
+            let end = (start + ALBUM_LIMIT).min(post.images.len());
+            if end - start == 1 {
+                post.images[start].tg_id =
+                    Some(publisher.publish_image(&post.images[start].url).await?);
+                post_store.put_post(post).await?;
+                continue;
+            }
+            // The text goes into the caption of the first album.
+            let caption = if start == 0 && !post.text.is_empty() {
+                Some(post.text.as_str())
+            } else {
+                None
+            };
+            let image_urls: Vec<&str> = post.images[start..end]
+                .iter()
+                .map(|image| image.url.as_str())
+                .collect();
+            let image_ids = publisher.publish_album(&image_urls, caption).await?;
+            let captioned = caption.is_some();
+            for (image, image_id) in post.images[start..end].iter_mut().zip(image_ids) {
+                image.tg_id = Some(image_id);
+            }
+            if captioned {
+                post.tg_id = post.images[start].tg_id.clone();
+            }
+            post_store.put_post(post).await?;
+        }
+        return Ok(());
+    }
+
+    for part in split_text(&post.text) {
+        let mut message_ids = text_ids(post);
+        message_ids.push(publisher.publish_text(&part).await?);
+        set_text_ids(post, message_ids);
+        post_store.put_post(post).await?;
+    }
+    for index in 0..post.images.len() {
+        let image_id = publisher.publish_image(&post.images[index].url).await?;
+        post.images[index].tg_id = Some(image_id);
+        post_store.put_post(post).await?;
+    }
+    Ok(())
+}
+
+// Brings the messages of `sent_post` in line with `post`, applying every change that succeeded
+// to `sent_post`, so that it describes the channel even when a later change fails. A post with
+// empty text has no text message, so text that failed to send is sent again on the next run.
+async fn update_post<P: Publisher>(
+    publisher: &P,
+    sent_post: &mut Post,
+    post: &Post,
+) -> Result<(), Error> {
+    let captioned = has_caption(sent_post);
+    let caption_id = sent_post.tg_id.clone().filter(|_| captioned);
+
+    if sent_post.text != post.text {
+        info!(
+            "post text has been updated from: {}, to: {}",
+            &sent_post.text, &post.text
+        );
+        update_text(publisher, sent_post, &post.text, captioned).await?;
+    } else if text_ids(sent_post).len() < split_text(&post.text).len() {
+        info!("post text has only been partly sent, sending the rest");
+        update_text(publisher, sent_post, &post.text, captioned).await?;
+    }
+
+    // Images are matched by key, so a re-signed URL of the same picture is just remembered for
+    // later. Pictures that are gone have their messages reused for the new ones, the rest of
+    // them are deleted and the rest of the new ones are sent.
+    let mut removed: Vec<String> = sent_post
+        .images
+        .iter()
+        .filter(|sent_image| !post.images.iter().any(|image| image.key == sent_image.key))
+        .filter_map(|sent_image| sent_image.tg_id.clone())
+        .rev()
+        .collect();
+    let mut images = Vec::with_capacity(post.images.len());
+    for new_image in &post.images {
+        let kept = sent_post
+            .images
+            .iter()
+            .find(|sent_image| sent_image.key == new_image.key)
+            .and_then(|sent_image| sent_image.tg_id.clone());
+        if let Some(message_id) = kept {
+            images.push(Image {
+                tg_id: Some(message_id),
+                ..new_image.clone()
+            });
+            continue;
+        }
+
+        let image_id = match removed.pop() {
+            Some(message_id) => {
+                info!(
+                    "image has been updated from: {}, to: {:?}",
+                    message_id, new_image
+                );
+                let caption = if sent_post.tg_id.as_ref() == Some(&message_id) {
+                    Some(sent_post.text.as_str())
+                } else {
+                    None
+                };
+                let result = publisher
+                    .replace_image(&message_id, &new_image.url, caption)
+                    .await;
+                match edited(result) {
+                    Err(ref e) if is_message_not_found(e) => {
+                        info!("image message is gone, sending it again");
+                        publisher.publish_image(&new_image.url).await?
                     }
-                    for image in &mut post.images {
-                        let image_id = telegram_client.send_image(&image.url).await?;
-                        image.tg_id = Some(image_id);
+                    result => {
+                        result?;
+                        message_id
                     }
-                    dynamo_client.put_post(&post).await?;
-                    std::thread::sleep(std::time::Duration::from_millis(500));
                 }
-                Some(mut sent_post) => {
-                    info!("post is already sent: {}", &sent_post.id);
-                    let mut updated = false;
-                    if sent_post.text != post.text {
-                        info!(
-                            "post text has been updated from: {}, to: {}",
-                            &sent_post.text, &post.text
-                        );
-                        if let Err(e) = telegram_client
-                            .edit_message_text(&sent_post.tg_id.as_ref().unwrap(), &post.text)
-                            .await
-                        {
-                            error!("Failed to update message text: {}", e);
-                        };
-                        updated = true;
-                    }
+            }
+            None => {
+                info!("image has been added: {:?}", new_image);
+                publisher.publish_image(&new_image.url).await?
+            }
+        };
+        images.push(Image {
+            tg_id: Some(image_id),
+            ..new_image.clone()
+        });
+    }
 
-                    for (sent_image, new_image) in
-                        sent_post.images.iter_mut().zip(post.images.iter_mut())
-                    {
-                        new_image.tg_id = sent_image.tg_id.clone();
-                        if sent_image.url != new_image.url {
-                            info!(
-                                "image has been updated from: {:?}, to: {:?}",
-                                sent_image, new_image
-                            );
-                            if let Err(e) = telegram_client
-                                .edit_message_image(
-                                    &sent_image.tg_id.as_ref().unwrap(),
-                                    &new_image.url,
-                                )
-                                .await
-                            {
-                                error!("Failed to update image: {}", e);
-                            };
-                            updated = true;
-                        }
-                    }
+    for message_id in removed.iter().rev() {
+        info!("image has been removed: {}", message_id);
+        ignore_missing(publisher.delete(message_id).await)?;
+    }
+    sent_post.images = images;
 
-                    if updated {
-                        post.tg_id = sent_post.tg_id.clone();
-                        dynamo_client.put_post(&post).await?;
-                    }
-                }
+    // Deleting the photo that carried the caption also deletes the text.
+    let caption_kept = sent_post
+        .images
+        .iter()
+        .any(|image| image.tg_id.is_some() && image.tg_id == caption_id);
+    if caption_id.is_some() && sent_post.tg_id == caption_id && !caption_kept {
+        move_caption(publisher, sent_post).await?;
+    }
+
+    Ok(())
+}
+
+// Puts the text of a post whose caption photo is gone onto the first remaining photo, or sends
+// it as its own message when no photo is left.
+async fn move_caption<P: Publisher>(publisher: &P, sent_post: &mut Post) -> Result<(), Error> {
+    let text = std::mem::take(&mut sent_post.text);
+    sent_post.tg_id = None;
+    let first_id = sent_post
+        .images
+        .first()
+        .and_then(|image| image.tg_id.clone());
+    if let Some(message_id) = first_id {
+        info!(
+            "caption photo has been removed, moving the caption to {}",
+            message_id
+        );
+        match edited(publisher.edit_caption(&message_id, &text).await) {
+            Err(ref e) if is_message_not_found(e) => {
+                info!("photo message is gone, sending the text separately");
+            }
+            result => {
+                result?;
+                sent_post.tg_id = Some(message_id);
+                sent_post.text = text;
+                return Ok(());
+            }
+        }
+    }
+    update_text_parts(publisher, sent_post, &text).await
+}
+
+async fn update_text<P: Publisher>(
+    publisher: &P,
+    sent_post: &mut Post,
+    text: &str,
+    captioned: bool,
+) -> Result<(), Error> {
+    let message_id = match sent_post.tg_id.clone() {
+        Some(message_id) if captioned => message_id,
+        _ => return update_text_parts(publisher, sent_post, text).await,
+    };
+
+    if text.is_empty() {
+        info!("post text has been removed");
+        ignore_missing(edited(publisher.edit_caption(&message_id, "").await))?;
+        sent_post.tg_id = None;
+        sent_post.text = String::new();
+        return Ok(());
+    }
+
+    if !fits_caption(text) {
+        info!("post text no longer fits in a caption, sending it separately");
+        ignore_missing(edited(publisher.edit_caption(&message_id, "").await))?;
+        sent_post.tg_id = None;
+        sent_post.text = String::new();
+        return update_text_parts(publisher, sent_post, text).await;
+    }
+    match edited(publisher.edit_caption(&message_id, text).await) {
+        Err(ref e) if is_message_not_found(e) => {
+            info!("caption message is gone, sending the text again");
+            sent_post.tg_id = None;
+            sent_post.text = String::new();
+            return update_text_parts(publisher, sent_post, text).await;
+        }
+        result => result?,
+    }
+    sent_post.text = String::from(text);
+    Ok(())
+}
+
+// Text too long for one message is sent as several. Parts that changed are edited in place, new
+// ones are sent after the last one and the ones no longer needed are deleted.
+async fn update_text_parts<P: Publisher>(
+    publisher: &P,
+    sent_post: &mut Post,
+    text: &str,
+) -> Result<(), Error> {
+    let sent_parts = split_text(&sent_post.text);
+    let parts = split_text(text);
+    let mut message_ids = text_ids(sent_post);
+
+    for (index, part) in parts.iter().enumerate() {
+        let message_id = match message_ids.get(index) {
+            Some(message_id) => message_id.clone(),
+            None => {
+                info!("sending part {} of the post text", index + 1);
+                message_ids.push(publisher.publish_text(part).await?);
+                set_text_ids(sent_post, message_ids.clone());
+                continue;
+            }
+        };
+        if sent_parts.get(index) == Some(part) {
+            continue;
+        }
+        match edited(publisher.edit_text(&message_id, part).await) {
+            Err(ref e) if is_message_not_found(e) => {
+                info!("text message is gone, sending it again");
+                message_ids[index] = publisher.publish_text(part).await?;
+                set_text_ids(sent_post, message_ids.clone());
             }
+            result => result?,
         }
     }
+
+    while message_ids.len() > parts.len() {
+        let message_id = &message_ids[message_ids.len() - 1];
+        info!(
+            "post text has become shorter, deleting message {}",
+            message_id
+        );
+        ignore_missing(publisher.delete(message_id).await)?;
+        message_ids.pop();
+        set_text_ids(sent_post, message_ids.clone());
+    }
+    sent_post.text = String::from(text);
     Ok(())
 }
+
+fn split_text(text: &str) -> Vec<String> {
+    formatter::split(text, MESSAGE_LIMIT)
+}
+
+// Ids of the messages carrying the text of the post, in order.
+fn text_ids(post: &Post) -> Vec<String> {
+    post.tg_id
+        .iter()
+        .chain(&post.continuation_ids)
+        .cloned()
+        .collect()
+}
+
+fn set_text_ids(post: &mut Post, message_ids: Vec<String>) {
+    let mut message_ids = message_ids.into_iter();
+    post.tg_id = message_ids.next();
+    post.continuation_ids = message_ids.collect();
+}
+
+fn fits_message(text: &str) -> bool {
+    formatter::length(text) <= MESSAGE_LIMIT
+}
+
+fn fits_caption(text: &str) -> bool {
+    formatter::length(text) <= CAPTION_LIMIT
+}
+
+// Posts sent as an album carry their text as the caption of one of the images, which is the
+// first one unless images were added in front of it or it was removed since.
+fn has_caption(post: &Post) -> bool {
+    post.tg_id.is_some() && post.images.iter().any(|image| image.tg_id == post.tg_id)
+}
+
+// Whether any message of the post made it to the channel.
+fn is_sent(post: &Post) -> bool {
+    post.tg_id.is_some() || post.images.iter().any(|image| image.tg_id.is_some())
+}
+
+// An edit that leaves the message as it already was is not a failure.
+fn edited(result: Result<(), Error>) -> Result<(), Error> {
+    match result {
+        Err(Error::Telegram(TelegramError::NotModified)) => Ok(()),
+        result => result,
+    }
+}
+
+// A message that is already gone, e.g. deleted by a channel admin, needs no further changes.
+fn ignore_missing(result: Result<(), Error>) -> Result<(), Error> {
+    match result {
+        Err(ref e) if is_message_not_found(e) => {
+            info!("message has already been deleted");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+fn is_message_not_found(error: &Error) -> bool {
+    matches!(error, Error::Telegram(TelegramError::MessageNotFound))
+}
+
+// Sent or edited now, a truncated post would be cut short until the next run.
+fn leave_truncated(post: &Post) -> Outcome {
+    info!(
+        "post is truncated, leaving it for the next run: {}",
+        &post.id
+    );
+    Outcome::Unchanged
+}
+
+// Looks up the stored copy of `post`, which keeps the chat it was sent to even when an edit
+// changes its kind.
+async fn find_stored<S: PostStore>(post_store: &S, post: &mut Post) -> Result<Option<Post>, Error> {
+    let stored = match post_store.get_post(&post.id).await? {
+        Some(stored) => Some(stored),
+        None => take_unprefixed_post(post_store, &post.id).await?,
+    };
+    if let Some(stored) = &stored {
+        post.kind = stored.kind;
+    }
+    Ok(stored)
+}
+
+// Skips the chat that posts of `kind` go to for the rest of the run, along with every other kind
+// routed to it. Returns whether any chat is left to forward posts to.
+fn disable_route<R: Router>(router: &R, kind: PostKind, disabled: &mut HashSet<PostKind>) -> bool {
+    if let Some(failed) = router.route(kind) {
+        warn!(
+            "chat of {} posts is unavailable for this run",
+            kind.as_str()
+        );
+        for other in PostKind::ALL.iter() {
+            if router
+                .route(*other)
+                .is_some_and(|publisher| ptr::eq(publisher, failed))
+            {
+                disabled.insert(*other);
+            }
+        }
+    }
+    PostKind::ALL
+        .iter()
+        .any(|kind| router.route(*kind).is_some() && !disabled.contains(kind))
+}
+
+// Failures that affect the whole chat, as every following call to it would fail the same way.
+fn is_chat_unavailable(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::Telegram(TelegramError::ChatNotFound) | Error::Telegram(TelegramError::Forbidden(_))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use memory_db::MemoryClient;
+
+    struct FakeSource {
+        posts: Vec<Post>,
+    }
+
+    #[async_trait]
+    impl PostSource for FakeSource {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        async fn fetch_posts(&self, _known: &[Post]) -> Result<Vec<Post>, Error> {
+            Ok(self.posts.clone())
+        }
+    }
+
+    // A source whose missing posts all turn out to still exist.
+    struct SkippingSource {
+        posts: Vec<Post>,
+    }
+
+    #[async_trait]
+    impl PostSource for SkippingSource {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        async fn fetch_posts(&self, _known: &[Post]) -> Result<Vec<Post>, Error> {
+            Ok(self.posts.clone())
+        }
+
+        async fn is_deleted(&self, _post: &Post) -> Result<bool, Error> {
+            Ok(false)
+        }
+    }
+
+    struct OtherSource {
+        posts: Vec<Post>,
+    }
+
+    #[async_trait]
+    impl PostSource for OtherSource {
+        fn name(&self) -> &str {
+            "other"
+        }
+
+        async fn fetch_posts(&self, _known: &[Post]) -> Result<Vec<Post>, Error> {
+            Ok(self.posts.clone())
+        }
+    }
+
+    // A source remembering the ids of the known posts it was given.
+    #[derive(Default)]
+    struct KnowingSource {
+        known_ids: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl PostSource for KnowingSource {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        async fn fetch_posts(&self, known: &[Post]) -> Result<Vec<Post>, Error> {
+            *self.known_ids.lock().unwrap() = known.iter().map(|post| post.id.clone()).collect();
+            Ok(vec![])
+        }
+    }
+
+    fn post(id: &str, text: &str, image_urls: &[&str]) -> Post {
+        let images = image_urls.iter().copied().map(Image::new).collect();
+        Post {
+            kind: PostKind::DailyMenu,
+            source: String::from("fake"),
+            ..Post::new(id, text, images)
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Call {
+        PublishText(String),
+        PublishImage(String),
+        PublishAlbum(Vec<String>, Option<String>),
+        EditText(String, String),
+        EditCaption(String, String),
+        ReplaceImage(String, String, Option<String>),
+        Delete(String),
+    }
+
+    type FailWith = fn() -> TelegramError;
+
+    #[derive(Default)]
+    struct RecordingPublisher {
+        calls: Mutex<Vec<Call>>,
+        last_id: AtomicUsize,
+        failures: Mutex<HashMap<&'static str, FailWith>>,
+    }
+
+    impl RecordingPublisher {
+        // Makes every following call of the given method, or for the given text, url or message
+        // id, fail with the error.
+        fn fail(&self, key: &'static str, error: FailWith) {
+            self.failures.lock().unwrap().insert(key, error);
+        }
+
+        fn check(&self, method: &str, argument: &str) -> Result<(), Error> {
+            let failures = self.failures.lock().unwrap();
+            match failures.get(method).or_else(|| failures.get(argument)) {
+                Some(error) => Err(error().into()),
+                None => Ok(()),
+            }
+        }
+
+        fn record(&self, call: Call) {
+            self.calls.lock().unwrap().push(call);
+        }
+
+        // Message ids are handed out sequentially, starting from 1.
+        fn next_id(&self) -> String {
+            (self.last_id.fetch_add(1, Ordering::SeqCst) + 1).to_string()
+        }
+
+        fn calls(&self) -> Vec<Call> {
+            self.calls.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    #[async_trait]
+    impl Publisher for RecordingPublisher {
+        async fn publish_text(&self, text: &str) -> Result<String, Error> {
+            self.record(Call::PublishText(text.into()));
+            self.check("publish_text", text)?;
+            Ok(self.next_id())
+        }
+
+        async fn publish_image(&self, image_url: &str) -> Result<String, Error> {
+            self.record(Call::PublishImage(image_url.into()));
+            self.check("publish_image", image_url)?;
+            Ok(self.next_id())
+        }
+
+        async fn publish_album(
+            &self,
+            image_urls: &[&str],
+            caption: Option<&str>,
+        ) -> Result<Vec<String>, Error> {
+            self.record(Call::PublishAlbum(
+                image_urls.iter().map(|url| String::from(*url)).collect(),
+                caption.map(String::from),
+            ));
+            self.check("publish_album", image_urls[0])?;
+            Ok(image_urls.iter().map(|_| self.next_id()).collect())
+        }
+
+        async fn edit_text(&self, message_id: &str, text: &str) -> Result<(), Error> {
+            self.record(Call::EditText(message_id.into(), text.into()));
+            self.check("edit_text", message_id)
+        }
+
+        async fn edit_caption(&self, message_id: &str, caption: &str) -> Result<(), Error> {
+            self.record(Call::EditCaption(message_id.into(), caption.into()));
+            self.check("edit_caption", message_id)
+        }
+
+        async fn replace_image(
+            &self,
+            message_id: &str,
+            image_url: &str,
+            caption: Option<&str>,
+        ) -> Result<(), Error> {
+            self.record(Call::ReplaceImage(
+                message_id.into(),
+                image_url.into(),
+                caption.map(String::from),
+            ));
+            self.check("replace_image", message_id)
+        }
+
+        async fn delete(&self, message_id: &str) -> Result<(), Error> {
+            self.record(Call::Delete(message_id.into()));
+            self.check("delete", message_id)
+        }
+    }
+
+    #[tokio::test]
+    async fn new_post_is_sent_and_stored() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::PublishText("text".into()),
+                Call::PublishImage("url".into())
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(
+            stored,
+            Post {
+                tg_id: Some(String::from("1")),
+                images: vec![Image {
+                    tg_id: Some(String::from("2")),
+                    ..Image::new("url")
+                }],
+                ..post("fake:1", "text", &[])
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn sources_know_their_stored_posts() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&sent_post("1", "menu", "10", &[]))
+            .await
+            .unwrap();
+        store
+            .put_post(&Post {
+                id: storage_key("other", "2"),
+                ..post("2", "news", &[])
+            })
+            .await
+            .unwrap();
+        let sources = [KnowingSource::default()];
+        process_posts_with(&sources, &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(*sources[0].known_ids.lock().unwrap(), vec!["1"]);
+    }
+
+    #[tokio::test]
+    async fn store_is_scanned_once_for_recent_posts() {
+        let store = RecordingStore::default();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&Post {
+                published: published_hours_ago(24),
+                ..sent_post("1", "menu", "10", &[])
+            })
+            .await
+            .unwrap();
+        store
+            .put_post(&Post {
+                published: published_hours_ago(31 * 24),
+                ..sent_post("2", "menu", "11", &[])
+            })
+            .await
+            .unwrap();
+        let sources = [KnowingSource::default(), KnowingSource::default()];
+        process_posts_with(&sources, &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(store.scans.load(Ordering::SeqCst), 1);
+        assert_eq!(*sources[0].known_ids.lock().unwrap(), vec!["1"]);
+        assert_eq!(*sources[1].known_ids.lock().unwrap(), vec!["1"]);
+    }
+
+    #[tokio::test]
+    async fn posts_are_forwarded_to_the_chat_of_their_kind() {
+        let store = MemoryClient::new();
+        let routes = Routes::new()
+            .with(
+                &[PostKind::DailyMenu, PostKind::WeeklyMenu],
+                RecordingPublisher::default(),
+            )
+            .with(&[PostKind::Announcement], RecordingPublisher::default());
+        let source = FakeSource {
+            posts: vec![
+                post("1", "menu", &[]),
+                Post {
+                    kind: PostKind::Announcement,
+                    ..post("2", "news", &[])
+                },
+                Post {
+                    kind: PostKind::Promotion,
+                    ..post("3", "sale", &[])
+                },
+            ],
+        };
+        let summary = process_posts_with(&[source], &store, &routes, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.sent, 2);
+        let menus = routes.route(PostKind::DailyMenu).unwrap();
+        let news = routes.route(PostKind::Announcement).unwrap();
+        assert_eq!(menus.calls(), vec![Call::PublishText("menu".into())]);
+        assert_eq!(news.calls(), vec![Call::PublishText("news".into())]);
+        assert!(routes.route(PostKind::Promotion).is_none());
+        assert!(store.get_post("fake:3").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn post_stays_in_its_chat_when_its_kind_changes() {
+        let store = MemoryClient::new();
+        store
+            .put_post(&sent_post("1", "menu", "10", &[]))
+            .await
+            .unwrap();
+        let routes = Routes::new()
+            .with(&[PostKind::DailyMenu], RecordingPublisher::default())
+            .with(&[PostKind::Announcement], RecordingPublisher::default());
+        let source = FakeSource {
+            posts: vec![Post {
+                kind: PostKind::Announcement,
+                ..post("1", "cancelled", &[])
+            }],
+        };
+        process_posts_with(&[source], &store, &routes, &Config::default())
+            .await
+            .unwrap();
+
+        let menus = routes.route(PostKind::DailyMenu).unwrap();
+        let news = routes.route(PostKind::Announcement).unwrap();
+        assert_eq!(
+            menus.calls(),
+            vec![Call::EditText("10".into(), "cancelled".into())]
+        );
+        assert_eq!(news.calls(), vec![]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.kind, PostKind::DailyMenu);
+    }
+
+    fn published_hours_ago(hours: u64) -> Option<u64> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Some(now.as_secs() - hours * 60 * 60)
+    }
+
+    #[tokio::test]
+    async fn stale_posts_are_recorded_without_sending() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let source = FakeSource {
+            posts: vec![
+                Post {
+                    published: published_hours_ago(1),
+                    ..post("1", "today", &[])
+                },
+                Post {
+                    published: published_hours_ago(48),
+                    ..post("2", "two days ago", &[])
+                },
+                post("3", "unknown", &[]),
+            ],
+        };
+        let config = Config {
+            max_age: Some(Duration::from_secs(24 * 60 * 60)),
+            ..Config::default()
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &config)
+            .await
+            .unwrap();
+
+        assert_eq!((summary.sent, summary.skipped), (2, 1));
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::PublishText("today".into()),
+                Call::PublishText("unknown".into())
+            ]
+        );
+        let stored = store.get_post("fake:2").await.unwrap().unwrap();
+        assert_eq!(stored.status, PostStatus::Skipped);
+        assert_eq!(stored.tg_id, None);
+    }
+
+    #[tokio::test]
+    async fn seeded_posts_are_never_sent() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let seed = Config {
+            seed: true,
+            ..Config::default()
+        };
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url"])],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &seed)
+            .await
+            .unwrap();
+        assert_eq!(summary.skipped, 1);
+
+        let source = FakeSource {
+            posts: vec![post("1", "updated", &["url"]), post("2", "new", &[])],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!((summary.sent, summary.updated, summary.skipped), (1, 0, 0));
+        assert_eq!(publisher.calls(), vec![Call::PublishText("new".into())]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.status, PostStatus::Skipped);
+        assert_eq!(stored.text, "text");
+    }
+
+    #[test]
+    fn parses_routes() {
+        assert_eq!(
+            parse_routes("daily_menu, weekly_menu=@kliversala; promotion=-100123;"),
+            Some(vec![
+                (
+                    vec![PostKind::DailyMenu, PostKind::WeeklyMenu],
+                    String::from("@kliversala")
+                ),
+                (vec![PostKind::Promotion], String::from("-100123")),
+            ])
+        );
+        assert_eq!(parse_routes("menu=@kliversala"), None);
+        assert_eq!(parse_routes("daily_menu"), None);
+        assert_eq!(parse_routes("daily_menu="), None);
+    }
+
+    #[tokio::test]
+    async fn already_sent_post_is_skipped() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let sources = [FakeSource {
+            posts: vec![post("1", "text", &[])],
+        }];
+        process_posts_with(&sources, &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+        assert_eq!(publisher.calls(), vec![Call::PublishText("text".into())]);
+
+        process_posts_with(&sources, &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+        assert_eq!(publisher.calls(), vec![]);
+        assert_eq!(store.scan_posts(0).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn updated_post_text_is_edited() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&sent_post("1", "original", "10", &[]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "updated", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::EditText("10".into(), "updated".into())]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.text, "updated");
+        assert_eq!(stored.tg_id, Some(String::from("10")));
+    }
+
+    // Text of a paragraph per letter, each too long to share a message with another.
+    fn paragraphs(letters: &str) -> String {
+        letters
+            .chars()
+            .map(|letter| letter.to_string().repeat(3000))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    fn long_post(text: &str, message_ids: &[&str]) -> Post {
+        Post {
+            id: String::from("fake:1"),
+            tg_id: Some(String::from(message_ids[0])),
+            continuation_ids: message_ids[1..]
+                .iter()
+                .map(|id| String::from(*id))
+                .collect(),
+            ..post("1", text, &[])
+        }
+    }
+
+    #[tokio::test]
+    async fn long_post_text_is_sent_in_parts() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let source = FakeSource {
+            posts: vec![post("1", &paragraphs("ab"), &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::PublishText(paragraphs("a")),
+                Call::PublishText(paragraphs("b")),
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored, long_post(&paragraphs("ab"), &["1", "2"]));
+    }
+
+    #[tokio::test]
+    async fn longer_post_text_edits_changed_parts_and_sends_new_ones() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&long_post(&paragraphs("ab"), &["10", "11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", &paragraphs("acd"), &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::EditText("11".into(), paragraphs("c")),
+                Call::PublishText(paragraphs("d")),
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored, long_post(&paragraphs("acd"), &["10", "11", "1"]));
+    }
+
+    #[tokio::test]
+    async fn shorter_post_text_deletes_parts_no_longer_needed() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&long_post(&paragraphs("abc"), &["10", "11", "12"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::EditText("10".into(), "text".into()),
+                Call::Delete("12".into()),
+                Call::Delete("11".into()),
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored, long_post("text", &["10"]));
+    }
+
+    #[tokio::test]
+    async fn interrupted_long_post_text_sends_missing_parts() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&Post {
+                status: PostStatus::Sending,
+                ..long_post(&paragraphs("ab"), &["10"])
+            })
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", &paragraphs("ab"), &[])],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.sent, 1);
+        assert_eq!(publisher.calls(), vec![Call::PublishText(paragraphs("b"))]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored, long_post(&paragraphs("ab"), &["10", "1"]));
+    }
+
+    #[tokio::test]
+    async fn deleted_long_post_deletes_every_part() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&Post {
+                id: String::from("fake:300"),
+                ..long_post(&paragraphs("ab"), &["10", "11"])
+            })
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("200", "", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::Delete("10".into()), Call::Delete("11".into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn deleted_long_post_is_withdrawn_with_notice_only() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&Post {
+                id: String::from("fake:300"),
+                ..long_post(&format!("{}\n\ntext", "a".repeat(4090)), &["10", "11"])
+            })
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("200", "", &[])],
+        };
+        let config = Config {
+            deleted_posts: DeletedPosts::Withdraw,
+            ..Config::default()
+        };
+        process_posts_with(&[source], &store, &publisher, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::EditText("10".into(), WITHDRAWN_NOTICE.into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn updated_post_image_is_replaced() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&Post {
+                tg_id: None,
+                images: vec![Image {
+                    tg_id: Some(String::from("10")),
+                    ..Image::new("url")
+                }],
+                ..sent_post("1", "", "10", &[])
+            })
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "", &["new-url"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::ReplaceImage("10".into(), "new-url".into(), None)]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.images[0].url, "new-url");
+        assert_eq!(stored.images[0].tg_id, Some(String::from("10")));
+    }
+
+    fn album_post(id: &str, text: &str, image_ids: &[&str]) -> Post {
+        let images = image_ids
+            .iter()
+            .enumerate()
+            .map(|(index, image_id)| Image {
+                tg_id: Some(String::from(*image_id)),
+                ..Image::new(&format!("url-{}", index + 1))
+            })
+            .collect();
+        Post {
+            tg_id: if text.is_empty() {
+                None
+            } else {
+                Some(String::from(image_ids[0]))
+            },
+            images,
+            ..post(&storage_key("fake", id), text, &[])
+        }
+    }
+
+    #[tokio::test]
+    async fn multi_image_post_is_sent_as_album() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-1", "url-2"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::PublishAlbum(
+                vec!["url-1".into(), "url-2".into()],
+                Some("text".into())
+            )]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored, album_post("1", "text", &["1", "2"]));
+    }
+
+    #[tokio::test]
+    async fn large_album_is_split_into_chunks() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let urls = (1..=11)
+            .map(|index| format!("url-{}", index))
+            .collect::<Vec<_>>();
+        let source = FakeSource {
+            posts: vec![post(
+                "1",
+                "",
+                &urls.iter().map(String::as_str).collect::<Vec<_>>(),
+            )],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::PublishAlbum(urls[..10].to_vec(), None),
+                Call::PublishImage(urls[10].clone())
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, None);
+        assert_eq!(stored.images.len(), 11);
+    }
+
+    #[tokio::test]
+    async fn multi_image_post_with_long_text_is_sent_separately() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let text = "a".repeat(CAPTION_LIMIT + 1);
+        let source = FakeSource {
+            posts: vec![post("1", &text, &["url-1", "url-2"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::PublishText(text),
+                Call::PublishImage("url-1".into()),
+                Call::PublishImage("url-2".into())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn album_caption_is_edited() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&album_post("1", "original", &["10", "11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "updated", &["url-1", "new-url"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::EditCaption("10".into(), "updated".into()),
+                Call::ReplaceImage("11".into(), "new-url".into(), None)
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some(String::from("10")));
+        assert_eq!(stored.text, "updated");
+    }
+
+    #[tokio::test]
+    async fn replaced_album_cover_keeps_caption() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&album_post("1", "text", &["10", "11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["new-url", "url-2"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::ReplaceImage(
+                "10".into(),
+                "new-url".into(),
+                Some("text".into())
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn removed_album_cover_moves_caption() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&album_post("1", "text", &["10", "11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-2"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::Delete("10".into()),
+                Call::EditCaption("11".into(), "text".into())
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some(String::from("11")));
+        assert_eq!(stored.text, "text");
+    }
+
+    #[tokio::test]
+    async fn image_added_before_album_cover_keeps_caption() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&album_post("1", "original", &["10", "11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "updated", &["url-new", "url-1", "url-2"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+        process_posts_with(
+            &[FakeSource {
+                posts: vec![post("1", "edited", &["url-new", "url-1", "url-2"])],
+            }],
+            &store,
+            &publisher,
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::EditCaption("10".into(), "updated".into()),
+                Call::PublishImage("url-new".into()),
+                Call::EditCaption("10".into(), "edited".into())
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some(String::from("10")));
+        assert_eq!(stored.images[0].tg_id, Some(String::from("1")));
+    }
+
+    #[tokio::test]
+    async fn album_caption_too_long_is_moved_to_message() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&album_post("1", "original", &["10", "11"]))
+            .await
+            .unwrap();
+        let text = "a".repeat(CAPTION_LIMIT + 1);
+        let source = FakeSource {
+            posts: vec![post("1", &text, &["url-1", "url-2"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::EditCaption("10".into(), "".into()),
+                Call::PublishText(text)
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some(String::from("1")));
+        assert_eq!(stored.images[0].tg_id, Some(String::from("10")));
+    }
+
+    fn sent_post(id: &str, text: &str, message_id: &str, image_ids: &[&str]) -> Post {
+        let images = image_ids
+            .iter()
+            .map(|image_id| Image {
+                tg_id: Some(String::from(*image_id)),
+                ..Image::new(&format!("url-{}", image_id))
+            })
+            .collect();
+        Post {
+            tg_id: Some(String::from(message_id)),
+            images,
+            ..post(&storage_key("fake", id), text, &[])
+        }
+    }
+
+    #[tokio::test]
+    async fn deleted_post_messages_are_deleted() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        for post in &[
+            sent_post("100", "old", "1", &[]),
+            sent_post("200", "kept", "2", &[]),
+            sent_post("300", "deleted", "3", &["4"]),
+        ] {
+            store.put_post(post).await.unwrap();
+        }
+        let source = FakeSource {
+            posts: vec![post("200", "kept", &[]), post("400", "", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::Delete("3".into()), Call::Delete("4".into())]
+        );
+        assert_eq!(store.get_post("fake:300").await.unwrap(), None);
+        assert!(store.get_post("fake:100").await.unwrap().is_some());
+        assert!(store.get_post("fake:200").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn deleted_album_messages_are_deleted_once() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&album_post("300", "text", &["10", "11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("200", "", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::Delete("10".into()), Call::Delete("11".into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn deleted_post_is_withdrawn() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&sent_post("300", "text", "3", &["4"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("200", "", &[])],
+        };
+        let config = Config {
+            deleted_posts: DeletedPosts::Withdraw,
+            ..Config::default()
+        };
+        process_posts_with(&[source], &store, &publisher, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::EditText(
+                "3".into(),
+                format!("{}\n\ntext", WITHDRAWN_NOTICE)
+            )]
+        );
+        assert_eq!(store.get_post("fake:300").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn nothing_is_deleted_when_no_posts_are_fetched() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&sent_post("300", "text", "3", &[]))
+            .await
+            .unwrap();
+        let source = FakeSource { posts: vec![] };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(publisher.calls(), vec![]);
+        assert!(store.get_post("fake:300").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn added_image_is_sent() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&sent_post("1", "text", "10", &["11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-11", "url-new"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::PublishImage("url-new".into())]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        let image_ids: Vec<_> = stored
+            .images
+            .iter()
+            .map(|image| image.tg_id.clone())
+            .collect();
+        assert_eq!(image_ids, vec![Some("11".into()), Some("1".into())]);
+    }
+
+    #[tokio::test]
+    async fn removed_image_is_deleted() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&sent_post("1", "text", "10", &["11", "12", "13"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-11", "url-13"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(publisher.calls(), vec![Call::Delete("12".into())]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(
+            stored.images,
+            vec![
+                Image {
+                    url: "url-11".into(),
+                    thumbnail_url: None,
+                    key: "url-11".into(),
+                    tg_id: Some("11".into())
+                },
+                Image {
+                    url: "url-13".into(),
+                    thumbnail_url: None,
+                    key: "url-13".into(),
+                    tg_id: Some("13".into())
+                }
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn inserted_image_leaves_others_alone() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&sent_post("1", "text", "10", &["11", "12"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-new", "url-11", "url-12"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::PublishImage("url-new".into())]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(
+            stored
+                .images
+                .iter()
+                .map(|image| image.tg_id.clone().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["1", "11", "12"]
+        );
+    }
+
+    #[tokio::test]
+    async fn truncated_post_is_left_untouched() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let sent = sent_post("1", "menu\n\nsoup of the day", "10", &["11", "12"]);
+        store.put_post(&sent).await.unwrap();
+        let source = FakeSource {
+            posts: vec![
+                Post {
+                    truncated: true,
+                    ..post("1", "menu", &["url-11"])
+                },
+                Post {
+                    truncated: true,
+                    ..post("2", "news", &[])
+                },
+            ],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.sent, 0);
+        assert_eq!(publisher.calls(), vec![]);
+        assert_eq!(store.get_post("fake:1").await.unwrap().unwrap(), sent);
+        assert!(store.get_post("fake:2").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn truncated_post_is_recorded_in_seed_mode() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let source = FakeSource {
+            posts: vec![Post {
+                truncated: true,
+                ..post("1", "menu", &[])
+            }],
+        };
+        let config = Config {
+            seed: true,
+            ..Config::default()
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(publisher.calls(), vec![]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.status, PostStatus::Skipped);
+    }
+
+    #[tokio::test]
+    async fn image_without_message_is_sent() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let mut sent = sent_post("1", "text", "10", &["11"]);
+        sent.images[0].tg_id = None;
+        store.put_post(&sent).await.unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-11"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(publisher.calls(), vec![Call::PublishImage("url-11".into())]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.images[0].tg_id, Some("1".into()));
+    }
+
+    #[tokio::test]
+    async fn removing_album_images_resends_caption_as_message() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&album_post("1", "text", &["10", "11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::Delete("10".into()),
+                Call::Delete("11".into()),
+                Call::PublishText("text".into())
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some("1".into()));
+        assert_eq!(stored.images, vec![]);
+    }
+
+    #[tokio::test]
+    async fn text_and_image_transitions() {
+        let text_only = || sent_post("1", "text", "10", &[]);
+        let image_only = || {
+            let mut post = sent_post("1", "", "", &["11"]);
+            post.tg_id = None;
+            post
+        };
+        let text_and_image = || sent_post("1", "text", "10", &["11"]);
+        let empty = || {
+            let mut post = sent_post("1", "", "", &[]);
+            post.tg_id = None;
+            post
+        };
+        let cases = vec![
+            (text_only(), post("1", "text", &[]), vec![], Some("10")),
+            (
+                text_only(),
+                post("1", "", &[]),
+                vec![Call::Delete("10".into())],
+                None,
+            ),
+            (
+                text_only(),
+                post("1", "", &["url-new"]),
+                vec![
+                    Call::Delete("10".into()),
+                    Call::PublishImage("url-new".into()),
+                ],
+                None,
+            ),
+            (
+                text_only(),
+                post("1", "text", &["url-new"]),
+                vec![Call::PublishImage("url-new".into())],
+                Some("10"),
+            ),
+            (image_only(), post("1", "", &["url-11"]), vec![], None),
+            (
+                image_only(),
+                post("1", "text", &["url-11"]),
+                vec![Call::PublishText("text".into())],
+                Some("1"),
+            ),
+            (
+                image_only(),
+                post("1", "text", &[]),
+                vec![Call::PublishText("text".into()), Call::Delete("11".into())],
+                Some("1"),
+            ),
+            (
+                image_only(),
+                post("1", "", &[]),
+                vec![Call::Delete("11".into())],
+                None,
+            ),
+            (
+                text_and_image(),
+                post("1", "text", &["url-11"]),
+                vec![],
+                Some("10"),
+            ),
+            (
+                text_and_image(),
+                post("1", "", &["url-11"]),
+                vec![Call::Delete("10".into())],
+                None,
+            ),
+            (
+                text_and_image(),
+                post("1", "text", &[]),
+                vec![Call::Delete("11".into())],
+                Some("10"),
+            ),
+            (
+                text_and_image(),
+                post("1", "", &[]),
+                vec![Call::Delete("10".into()), Call::Delete("11".into())],
+                None,
+            ),
+            (
+                album_post("1", "text", &["11", "12"]),
+                post("1", "", &["url-1", "url-2"]),
+                vec![Call::EditCaption("11".into(), "".into())],
+                None,
+            ),
+            (empty(), post("1", "", &[]), vec![], None),
+            (
+                empty(),
+                post("1", "text", &[]),
+                vec![Call::PublishText("text".into())],
+                Some("1"),
+            ),
+            (
+                empty(),
+                post("1", "text", &["url-new"]),
+                vec![
+                    Call::PublishText("text".into()),
+                    Call::PublishImage("url-new".into()),
+                ],
+                Some("1"),
+            ),
+        ];
+
+        for (sent, new, calls, tg_id) in cases {
+            let description = format!("{:?} -> {:?}", sent, new);
+            let store = MemoryClient::new();
+            let publisher = RecordingPublisher::default();
+            store.put_post(&sent).await.unwrap();
+            let source = FakeSource {
+                posts: vec![new.clone()],
+            };
+            process_posts_with(&[source], &store, &publisher, &Config::default())
+                .await
+                .unwrap();
+
+            assert_eq!(publisher.calls(), calls, "{}", description);
+            let stored = store.get_post("fake:1").await.unwrap().unwrap();
+            assert_eq!(stored.tg_id.as_deref(), tg_id, "{}", description);
+            assert_eq!(stored.text, new.text, "{}", description);
+            assert_eq!(stored.images.len(), new.images.len(), "{}", description);
+        }
+    }
+
+    #[tokio::test]
+    async fn unmodified_edit_is_not_a_failure() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        publisher.fail("edit_text", || TelegramError::NotModified);
+        store
+            .put_post(&sent_post("1", "original", "10", &[]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "updated", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::EditText("10".into(), "updated".into())]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.text, "updated");
+        assert_eq!(stored.tg_id, Some(String::from("10")));
+    }
+
+    #[tokio::test]
+    async fn missing_text_message_is_sent_again() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        publisher.fail("edit_text", || TelegramError::MessageNotFound);
+        store
+            .put_post(&sent_post("1", "original", "10", &[]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "updated", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::EditText("10".into(), "updated".into()),
+                Call::PublishText("updated".into())
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some(String::from("1")));
+    }
+
+    #[tokio::test]
+    async fn missing_image_message_is_sent_again() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        publisher.fail("replace_image", || TelegramError::MessageNotFound);
+        store
+            .put_post(&sent_post("1", "text", "10", &["11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-new"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::ReplaceImage("11".into(), "url-new".into(), None),
+                Call::PublishImage("url-new".into())
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.images[0].tg_id, Some(String::from("1")));
+    }
+
+    #[tokio::test]
+    async fn already_deleted_messages_are_forgotten() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        publisher.fail("delete", || TelegramError::MessageNotFound);
+        for post in &[
+            sent_post("100", "kept", "1", &[]),
+            sent_post("200", "deleted", "2", &[]),
+        ] {
+            store.put_post(post).await.unwrap();
+        }
+        let source = FakeSource {
+            posts: vec![post("100", "kept", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(publisher.calls(), vec![Call::Delete("2".into())]);
+        assert_eq!(store.get_post("fake:200").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn skipped_post_is_not_deleted() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        for post in &[
+            sent_post("100", "kept", "1", &[]),
+            sent_post("200", "skipped", "2", &[]),
+        ] {
+            store.put_post(post).await.unwrap();
+        }
+        let source = SkippingSource {
+            posts: vec![post("100", "kept", &[])],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.removed, 0);
+        assert_eq!(publisher.calls(), vec![]);
+        assert!(store.get_post("fake:200").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn deletions_are_limited_per_run() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        for post in &[
+            sent_post("100", "kept", "1", &[]),
+            sent_post("200", "deleted", "2", &[]),
+            sent_post("300", "deleted", "3", &[]),
+            sent_post("400", "deleted", "4", &[]),
+        ] {
+            store.put_post(post).await.unwrap();
+        }
+        let source = FakeSource {
+            posts: vec![post("100", "kept", &[])],
+        };
+        let config = Config {
+            max_deletions: 2,
+            ..Config::default()
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.removed, 2);
+        assert_eq!(store.scan_posts(0).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn forbidden_chat_is_chat_unavailable() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        publisher.fail("edit_text", || {
+            TelegramError::Forbidden("Forbidden: bot was kicked from the channel chat".into())
+        });
+        store
+            .put_post(&sent_post("1", "original", "10", &[]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "updated", &[])],
+        };
+        let result = process_posts_with(&[source], &store, &publisher, &Config::default()).await;
+
+        assert!(matches!(
+            result,
+            Err(Error::Telegram(TelegramError::Forbidden(_)))
+        ));
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.text, "original");
+    }
+
+    #[tokio::test]
+    async fn forbidden_chat_only_stops_its_route() {
+        let store = MemoryClient::new();
+        let routes = Routes::new()
+            .with(
+                &[PostKind::DailyMenu, PostKind::WeeklyMenu],
+                RecordingPublisher::default(),
+            )
+            .with(&[PostKind::Announcement], RecordingPublisher::default());
+        let menus = routes.route(PostKind::DailyMenu).unwrap();
+        let news = routes.route(PostKind::Announcement).unwrap();
+        menus.fail("publish_text", || {
+            TelegramError::Forbidden("Forbidden: bot was kicked from the channel chat".into())
+        });
+        let source = FakeSource {
+            posts: vec![
+                post("1", "menu", &[]),
+                Post {
+                    kind: PostKind::WeeklyMenu,
+                    ..post("2", "weekly menu", &[])
+                },
+                Post {
+                    kind: PostKind::Announcement,
+                    ..post("3", "news", &[])
+                },
+            ],
+        };
+        let summary = process_posts_with(&[source], &store, &routes, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.sent, 1);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].post_id, Some("fake:1".into()));
+        assert_eq!(menus.calls(), vec![Call::PublishText("menu".into())]);
+        assert_eq!(news.calls(), vec![Call::PublishText("news".into())]);
+        assert!(store.get_post("fake:2").await.unwrap().is_none());
+    }
+
+    fn bad_request() -> TelegramError {
+        TelegramError::Api {
+            error_code: 400,
+            description: "Bad Request: wrong file identifier/HTTP URL specified".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_post_does_not_stop_other_posts() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        publisher.fail("expired-url", bad_request);
+        let sources = [FakeSource {
+            posts: vec![post("1", "one", &["expired-url"]), post("2", "two", &[])],
+        }];
+        let summary = process_posts_with(&sources, &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.sent, 1);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(summary.failures[0].post_id, Some("fake:1".into()));
+        assert_eq!(
+            publisher.calls(),
+            vec![
+                Call::PublishText("one".into()),
+                Call::PublishImage("expired-url".into()),
+                Call::PublishText("two".into())
+            ]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some("1".into()));
+        assert_eq!(stored.images[0].tg_id, None);
+        assert!(store.get_post("fake:2").await.unwrap().is_some());
+
+        // The next run only sends the image that is still missing.
+        let publisher = RecordingPublisher::default();
+        let summary = process_posts_with(&sources, &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+        assert_eq!(summary.updated, 1);
+        assert!(summary.failures.is_empty());
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::PublishImage("expired-url".into())]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some("1".into()));
+        assert_eq!(stored.images[0].tg_id, Some("1".into()));
+    }
+
+    #[tokio::test]
+    async fn unsent_post_is_not_stored() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        publisher.fail("publish_text", bad_request);
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url"])],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.sent, 0);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(publisher.calls(), vec![Call::PublishText("text".into())]);
+        assert_eq!(store.get_post("fake:1").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn partially_sent_album_keeps_caption() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let image_urls: Vec<String> = (1..=11).map(|i| format!("url-{}", i)).collect();
+        let image_urls: Vec<&str> = image_urls.iter().map(String::as_str).collect();
+        publisher.fail("url-11", bad_request);
+        let source = FakeSource {
+            posts: vec![post("1", "text", &image_urls)],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.failures.len(), 1);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some("1".into()));
+        assert_eq!(stored.images[9].tg_id, Some("10".into()));
+        assert_eq!(stored.images[10].tg_id, None);
+    }
+
+    #[tokio::test]
+    async fn failed_edit_is_retried_on_next_run() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        publisher.fail("edit_text", bad_request);
+        store
+            .put_post(&sent_post("1", "original", "10", &["11"]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "updated", &["url-12"])],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.updated, 0);
+        assert_eq!(summary.failures.len(), 1);
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::EditText("10".into(), "updated".into())]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored, sent_post("1", "original", "10", &["11"]));
+    }
+
+    #[tokio::test]
+    async fn failed_removal_is_retried_on_next_run() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        publisher.fail("delete", bad_request);
+        for post in &[
+            sent_post("100", "kept", "1", &[]),
+            sent_post("200", "deleted", "2", &[]),
+        ] {
+            store.put_post(post).await.unwrap();
+        }
+        let source = FakeSource {
+            posts: vec![post("100", "kept", &[])],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.removed, 0);
+        assert_eq!(summary.failures[0].post_id, Some("fake:200".into()));
+        assert!(store.get_post("fake:200").await.unwrap().is_some());
+    }
+
+    // Keeps a copy of every post written to the wrapped store.
+    #[derive(Default)]
+    struct RecordingStore {
+        store: MemoryClient,
+        puts: Mutex<Vec<Post>>,
+        scans: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl PostStore for RecordingStore {
+        async fn get_post(&self, id: &str) -> Result<Option<Post>, Error> {
+            self.store.get_post(id).await
+        }
+
+        async fn put_post(&self, post: &Post) -> Result<(), Error> {
+            self.puts.lock().unwrap().push(post.clone());
+            self.store.put_post(post).await
+        }
+
+        async fn scan_posts(&self, published_since: u64) -> Result<Vec<Post>, Error> {
+            self.scans.fetch_add(1, Ordering::SeqCst);
+            self.store.scan_posts(published_since).await
+        }
+
+        async fn delete_post(&self, id: &str) -> Result<(), Error> {
+            self.store.delete_post(id).await
+        }
+    }
+
+    #[tokio::test]
+    async fn message_ids_are_stored_as_they_arrive() {
+        let store = RecordingStore::default();
+        let publisher = RecordingPublisher::default();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-1"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        let puts: Vec<(PostStatus, Option<String>, Option<String>)> = store
+            .puts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|post| {
+                (
+                    post.status,
+                    post.tg_id.clone(),
+                    post.images[0].tg_id.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            puts,
+            vec![
+                (PostStatus::Sending, None, None),
+                (PostStatus::Sending, Some("1".into()), None),
+                (PostStatus::Sending, Some("1".into()), Some("2".into())),
+                (PostStatus::Sent, Some("1".into()), Some("2".into())),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn album_ids_are_stored_as_they_arrive() {
+        let store = RecordingStore::default();
+        let publisher = RecordingPublisher::default();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-1", "url-2"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        let puts = store.puts.lock().unwrap();
+        assert_eq!(puts.len(), 3);
+        assert_eq!(puts[1].status, PostStatus::Sending);
+        assert_eq!(puts[1].tg_id, Some("1".into()));
+        assert_eq!(puts[2].status, PostStatus::Sent);
+    }
+
+    #[tokio::test]
+    async fn interrupted_post_is_completed_without_resending() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let mut sending = sent_post("1", "text", "10", &["11"]);
+        sending.images[0].tg_id = None;
+        sending.status = PostStatus::Sending;
+        store.put_post(&sending).await.unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-11"])],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.sent, 1);
+        assert_eq!(publisher.calls(), vec![Call::PublishImage("url-11".into())]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.status, PostStatus::Sent);
+        assert_eq!(stored.tg_id, Some("10".into()));
+        assert_eq!(stored.images[0].tg_id, Some("1".into()));
+    }
+
+    #[tokio::test]
+    async fn interrupted_post_without_messages_is_sent_again() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let mut sending = post("1", "text", &["url-1", "url-2"]);
+        sending.status = PostStatus::Sending;
+        store.put_post(&sending).await.unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-1", "url-2"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::PublishAlbum(
+                vec!["url-1".into(), "url-2".into()],
+                Some("text".into())
+            )]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.status, PostStatus::Sent);
+        assert_eq!(stored.tg_id, Some("1".into()));
+    }
+
+    #[tokio::test]
+    async fn interrupted_post_sends_missing_text() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let mut sending = sent_post("1", "text", "10", &["11"]);
+        sending.tg_id = None;
+        sending.status = PostStatus::Sending;
+        store.put_post(&sending).await.unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &["url-11"])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(publisher.calls(), vec![Call::PublishText("text".into())]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.tg_id, Some("1".into()));
+        assert_eq!(stored.images[0].tg_id, Some("11".into()));
+    }
+
+    #[tokio::test]
+    async fn re_signed_image_url_is_not_replaced() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let mut sent = sent_post("1", "text", "10", &[]);
+        sent.images = vec![Image {
+            tg_id: Some("11".into()),
+            ..Image::new("https://scontent.xx.fbcdn.net/v/1_n.jpg?oh=1&oe=5E9A1B2C")
+        }];
+        store.put_post(&sent).await.unwrap();
+        let source = FakeSource {
+            posts: vec![post(
+                "1",
+                "text",
+                &["https://scontent.yy.fbcdn.net/v/1_n.jpg?oh=2&oe=5EC4F3E0"],
+            )],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(publisher.calls(), vec![]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(
+            stored.images[0].url,
+            "https://scontent.yy.fbcdn.net/v/1_n.jpg?oh=2&oe=5EC4F3E0"
+        );
+        assert_eq!(stored.images[0].tg_id, Some("11".into()));
+    }
+
+    #[tokio::test]
+    async fn changed_picture_is_replaced() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let mut sent = sent_post("1", "text", "10", &[]);
+        sent.images = vec![Image {
+            tg_id: Some("11".into()),
+            ..Image::new("https://scontent.xx.fbcdn.net/v/1_n.jpg?oe=5E9A1B2C")
+        }];
+        store.put_post(&sent).await.unwrap();
+        let source = FakeSource {
+            posts: vec![post(
+                "1",
+                "text",
+                &["https://scontent.xx.fbcdn.net/v/2_n.jpg?oe=5E9A1B2C"],
+            )],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            publisher.calls(),
+            vec![Call::ReplaceImage(
+                "11".into(),
+                "https://scontent.xx.fbcdn.net/v/2_n.jpg?oe=5E9A1B2C".into(),
+                None
+            )]
+        );
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored.images[0].key, "2_n.jpg");
+    }
+
+    #[tokio::test]
+    async fn sources_of_different_types_share_a_store() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        let sources: Vec<Box<dyn PostSource>> = vec![
+            Box::new(FakeSource {
+                posts: vec![post("1", "fake", &[])],
+            }),
+            Box::new(OtherSource {
+                posts: vec![post("1", "other", &[])],
+            }),
+        ];
+        let summary = process_posts_with(&sources, &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.sent, 2);
+        let fake = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(fake.text, "fake");
+        let other = store.get_post("other:1").await.unwrap().unwrap();
+        assert_eq!(other.text, "other");
+    }
+
+    #[tokio::test]
+    async fn posts_of_other_sources_are_not_deleted() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&Post {
+                id: String::from("other:300"),
+                ..sent_post("300", "text", "3", &[])
+            })
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("200", "", &[])],
+        };
+        process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert!(store.get_post("other:300").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn unprefixed_post_is_moved_under_source_name() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&Post {
+                id: String::from("1"),
+                ..sent_post("1", "text", "10", &[])
+            })
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![post("1", "text", &[])],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.sent, 0);
+        assert_eq!(publisher.calls(), vec![]);
+        assert_eq!(store.get_post("1").await.unwrap(), None);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(stored, sent_post("1", "text", "10", &[]));
+    }
+
+    #[tokio::test]
+    async fn details_of_sent_post_are_kept_up_to_date() {
+        let store = MemoryClient::new();
+        let publisher = RecordingPublisher::default();
+        store
+            .put_post(&sent_post("1", "text", "10", &[]))
+            .await
+            .unwrap();
+        let source = FakeSource {
+            posts: vec![Post {
+                permalink: Some(String::from("https://example.com/posts/1")),
+                author: Some(String::from("author")),
+                published: Some(1581059445),
+                ..post("1", "text", &[])
+            }],
+        };
+        let summary = process_posts_with(&[source], &store, &publisher, &Config::default())
+            .await
+            .unwrap();
+
+        assert_eq!(summary.updated, 0);
+        assert_eq!(publisher.calls(), vec![]);
+        let stored = store.get_post("fake:1").await.unwrap().unwrap();
+        assert_eq!(
+            stored.permalink,
+            Some(String::from("https://example.com/posts/1"))
+        );
+        assert_eq!(stored.author, Some(String::from("author")));
+        assert_eq!(stored.published, Some(1581059445));
+    }
+}
@@ -12,7 +12,10 @@ fn main() {
         let mut rt = Runtime::new().unwrap();
         rt.block_on(async {
             match process_posts().await {
-                Ok(()) => info!("successfully processed posts"),
+                Ok(summary) if summary.failures.is_empty() => {
+                    info!("successfully processed posts: {}", summary)
+                }
+                Ok(summary) => error!("some posts could not be processed: {}", summary),
                 Err(e) => error!("error occurred while processing posts: {}", e),
             }
         });
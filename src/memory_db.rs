@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use log::{debug, info};
+
+use crate::error::Error;
+use crate::sources::{Image, Post, PostKind, PostStatus};
+use crate::store::PostStore;
+
+#[derive(Clone, Debug, Default)]
+struct Record {
+    text: Option<String>,
+    message_id: Option<String>,
+    continuation_ids: Vec<String>,
+    images: Vec<String>,
+    image_keys: Vec<String>,
+    image_thumbnails: Vec<Option<String>>,
+    image_ids: Vec<Option<String>>,
+    status: Option<String>,
+    kind: Option<String>,
+    published: Option<u64>,
+    source: Option<String>,
+    permalink: Option<String>,
+    author: Option<String>,
+}
+
+#[derive(Default)]
+pub struct MemoryClient {
+    records: Mutex<HashMap<String, Record>>,
+}
+
+impl MemoryClient {
+    pub fn new() -> MemoryClient {
+        MemoryClient::default()
+    }
+}
+
+#[async_trait]
+impl PostStore for MemoryClient {
+    async fn get_post(&self, id: &str) -> Result<Option<Post>, Error> {
+        let records = self.records.lock().unwrap();
+        match records.get(id) {
+            Some(record) => {
+                info!("get_post: Ok(id: {})", id);
+                Ok(Some(build_post(id, record)))
+            }
+            None => {
+                info!("get_post: post {} not found", id);
+                Ok(None)
+            }
+        }
+    }
+
+    async fn put_post(&self, post: &Post) -> Result<(), Error> {
+        debug!("put_post: {:?}", post);
+        let record = build_record(post);
+        self.records.lock().unwrap().insert(post.id.clone(), record);
+        info!("put_post: Ok(id: {})", post.id);
+        Ok(())
+    }
+
+    async fn scan_posts(&self, published_since: u64) -> Result<Vec<Post>, Error> {
+        let records = self.records.lock().unwrap();
+        let posts: Vec<Post> = records
+            .iter()
+            .filter(|(_, record)| {
+                record
+                    .published
+                    .is_none_or(|published| published >= published_since)
+            })
+            .map(|(id, record)| build_post(id, record))
+            .collect();
+        info!("scan: Ok(count: {})", posts.len());
+        Ok(posts)
+    }
+
+    async fn delete_post(&self, id: &str) -> Result<(), Error> {
+        self.records.lock().unwrap().remove(id);
+        info!("delete_post: Ok(id: {})", id);
+        Ok(())
+    }
+}
+
+// Mirrors the attribute layout written by `DynamoClient::put_post`, so that
+// posts read back from memory look exactly like the ones read from DynamoDB.
+fn build_record(post: &Post) -> Record {
+    let mut record = Record::default();
+    if !post.text.is_empty() {
+        record.text = Some(post.text.clone());
+    }
+    if let Some(tg_id) = &post.tg_id {
+        if !tg_id.is_empty() {
+            record.message_id = Some(tg_id.clone());
+        }
+    }
+    record.continuation_ids = post.continuation_ids.clone();
+    for image in &post.images {
+        record.images.push(image.url.clone());
+        record.image_keys.push(image.key.clone());
+        record.image_thumbnails.push(image.thumbnail_url.clone());
+        record.image_ids.push(image.tg_id.clone());
+    }
+    record.status = Some(String::from(post.status.as_str()));
+    record.kind = Some(String::from(post.kind.as_str()));
+    record.published = post.published;
+    if !post.source.is_empty() {
+        record.source = Some(post.source.clone());
+    }
+    record.permalink = post.permalink.clone();
+    record.author = post.author.clone();
+    record
+}
+
+fn build_post(id: &str, record: &Record) -> Post {
+    let images = record
+        .images
+        .iter()
+        .zip(record.image_keys.iter())
+        .zip(record.image_thumbnails.iter())
+        .zip(record.image_ids.iter())
+        .map(|(((url, key), thumbnail_url), tg_id)| Image {
+            url: url.clone(),
+            thumbnail_url: thumbnail_url.clone(),
+            key: key.clone(),
+            tg_id: tg_id.clone(),
+        })
+        .collect();
+
+    let text = record.text.clone().unwrap_or_default();
+    Post {
+        id: String::from(id),
+        kind: PostKind::parse_stored(record.kind.as_deref(), &text),
+        published: record.published,
+        source: record.source.clone().unwrap_or_default(),
+        permalink: record.permalink.clone(),
+        author: record.author.clone(),
+        truncated: false,
+        text,
+        images,
+        tg_id: record.message_id.clone(),
+        continuation_ids: record.continuation_ids.clone(),
+        status: PostStatus::parse(record.status.as_deref()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post() -> Post {
+        let images = vec![
+            Image {
+                tg_id: Some(String::from("11")),
+                ..Image::new("url-1")
+            },
+            Image {
+                tg_id: Some(String::from("12")),
+                ..Image::full_size("url-2", "thumbnail-2")
+            },
+        ];
+        Post {
+            tg_id: Some(String::from("10")),
+            continuation_ids: vec![String::from("13")],
+            published: Some(1581059445),
+            source: String::from("facebook"),
+            permalink: Some(String::from(
+                "https://www.facebook.com/kantineKliversala/posts/1",
+            )),
+            author: Some(String::from("Kantīne \"Klīversala\"")),
+            ..Post::new("1", "text", images)
+        }
+    }
+
+    #[tokio::test]
+    async fn put_and_get_post() {
+        let client = MemoryClient::new();
+        client.put_post(&post()).await.unwrap();
+
+        let result = client.get_post("1").await.unwrap().unwrap();
+        assert_eq!(result, post());
+    }
+
+    #[tokio::test]
+    async fn get_missing_post() {
+        let client = MemoryClient::new();
+        assert_eq!(client.get_post("1").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn put_post_without_text_or_ids() {
+        let client = MemoryClient::new();
+        let post = Post {
+            tg_id: Some(String::from("")),
+            ..Post::new("1", "", vec![Image::new("url-1")])
+        };
+        client.put_post(&post).await.unwrap();
+
+        let result = client.get_post("1").await.unwrap().unwrap();
+        assert_eq!(result.tg_id, None);
+        assert_eq!(result.text, "");
+        assert_eq!(result.images, post.images);
+    }
+
+    #[tokio::test]
+    async fn scan_and_delete_posts() {
+        let client = MemoryClient::new();
+        client.put_post(&post()).await.unwrap();
+        assert_eq!(client.scan_posts(0).await.unwrap().len(), 1);
+
+        client.delete_post("1").await.unwrap();
+        assert_eq!(client.scan_posts(0).await.unwrap().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn scan_leaves_out_older_posts() {
+        let client = MemoryClient::new();
+        client.put_post(&post()).await.unwrap();
+        client
+            .put_post(&Post {
+                id: String::from("2"),
+                published: None,
+                ..post()
+            })
+            .await
+            .unwrap();
+
+        let posts = client.scan_posts(1581059446).await.unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].id, "2");
+        assert_eq!(client.scan_posts(1581059445).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn put_and_get_sending_post() {
+        let client = MemoryClient::new();
+        let mut post = post();
+        post.status = PostStatus::Sending;
+        client.put_post(&post).await.unwrap();
+
+        let result = client.get_post("1").await.unwrap().unwrap();
+        assert_eq!(result.status, PostStatus::Sending);
+    }
+}
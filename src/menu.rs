@@ -0,0 +1,472 @@
+use regex::Regex;
+
+const MONTHS: &[&str] = &[
+    "janvārī",
+    "februārī",
+    "martā",
+    "aprīlī",
+    "maijā",
+    "jūnijā",
+    "jūlijā",
+    "augustā",
+    "septembrī",
+    "oktobrī",
+    "novembrī",
+    "decembrī",
+];
+
+/// Lunch menu of a day, as announced by a post like "Pusdienu piedāvājums 7. februārī."
+#[derive(Clone, Debug, PartialEq)]
+pub struct Menu {
+    pub day: u32,
+    pub month: u32,
+    /// Taken from when the post was published, `None` if that is not known.
+    pub year: Option<i32>,
+    pub hours: Option<ServingHours>,
+    /// Announcements that come with the menu, like a shorter working day.
+    pub notices: Vec<String>,
+    pub offers: Vec<Offer>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServingHours {
+    pub from: Time,
+    pub to: Time,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Time {
+    pub hour: u32,
+    pub minute: u32,
+}
+
+/// A set lunch for a single price, like "Mazais pusdienu piedāvājums".
+#[derive(Clone, Debug, PartialEq)]
+pub struct Offer {
+    pub tier: Tier,
+    pub courses: Vec<Course>,
+    pub price_cents: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tier {
+    Small,
+    Large,
+    Other(String),
+}
+
+/// One line of an offer, the guest picks one of its options.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Course {
+    pub options: Vec<Dish>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dish {
+    pub kind: DishKind,
+    pub name: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DishKind {
+    Soup,
+    Main,
+    Salad,
+    Drink,
+    Dessert,
+}
+
+impl Menu {
+    /// Parses the text of a post published at `published`, returning `None` if it is not a lunch
+    /// menu.
+    pub fn parse(text: &str, published: Option<u64>) -> Option<Menu> {
+        lazy_static! {
+            static ref DATE: Regex =
+                Regex::new(r"^Pusdienu piedāvājums (\d{1,2})\. (\w+)").unwrap();
+            static ref HOURS: Regex =
+                Regex::new(r"(\d{1,2}):(\d{2}) ?- ?(\d{1,2}):(\d{2})").unwrap();
+        }
+
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        let date = DATE.captures(lines.next()?)?;
+        let day = date[1].parse().ok()?;
+        let month = MONTHS.iter().position(|month| *month == &date[2])? as u32 + 1;
+
+        let mut menu = Menu {
+            day,
+            month,
+            year: published.map(|published| menu_year(published, month)),
+            hours: None,
+            notices: vec![],
+            offers: vec![],
+        };
+        // Dish lines of the offer being read, a line ending in "vai" ("or") continues on the next.
+        let mut dish_lines: Vec<String> = vec![];
+        for line in lines {
+            if let Some(heading) = line.strip_suffix(':') {
+                finish_offer(&mut menu, &mut dish_lines);
+                menu.offers.push(Offer {
+                    tier: Tier::parse(heading),
+                    courses: vec![],
+                    price_cents: None,
+                });
+            } else if line.starts_with("!!") {
+                menu.notices
+                    .push(String::from(line.trim_matches(['!', ' '])));
+            } else if menu.offers.is_empty() {
+                if let (None, Some(hours)) = (&menu.hours, HOURS.captures(line)) {
+                    menu.hours = Some(ServingHours {
+                        from: Time {
+                            hour: hours[1].parse().ok()?,
+                            minute: hours[2].parse().ok()?,
+                        },
+                        to: Time {
+                            hour: hours[3].parse().ok()?,
+                            minute: hours[4].parse().ok()?,
+                        },
+                    });
+                }
+            } else if let Some(price) = line.strip_prefix('💸') {
+                let offer = menu.offers.last_mut()?;
+                offer.price_cents = offer.price_cents.or_else(|| parse_price(price));
+            } else if menu.offers.last()?.price_cents.is_none() {
+                match dish_lines.last_mut() {
+                    Some(last) if last.trim_end_matches(',').ends_with(" vai") => {
+                        last.push(' ');
+                        last.push_str(line);
+                    }
+                    _ => dish_lines.push(String::from(line)),
+                }
+            }
+        }
+        finish_offer(&mut menu, &mut dish_lines);
+
+        if menu.offers.is_empty() {
+            return None;
+        }
+        Some(menu)
+    }
+}
+
+impl Tier {
+    fn parse(heading: &str) -> Tier {
+        if heading.starts_with("Mazais") {
+            Tier::Small
+        } else if heading.starts_with("Lielais") {
+            Tier::Large
+        } else {
+            Tier::Other(String::from(heading))
+        }
+    }
+}
+
+impl DishKind {
+    // Dishes are marked with an emoji, the ones without take the kind from their name.
+    fn parse(option: &str) -> Option<DishKind> {
+        let emoji = option.chars().next()?;
+        let kind = match emoji {
+            '🍲' => DishKind::Soup,
+            '🥒' => DishKind::Salad,
+            '🍷' | '☕' => DishKind::Drink,
+            '🍰' => DishKind::Dessert,
+            '🍗' | '🥘' | '🌽' | '🐖' | '🐂' | '🐟' => DishKind::Main,
+            _ if option.contains("zupa") || option.contains("borščs") => DishKind::Soup,
+            _ if option.contains("salāti") => DishKind::Salad,
+            _ if option.contains("dzēriens") => DishKind::Drink,
+            _ if option.contains("deserts") => DishKind::Dessert,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+fn finish_offer(menu: &mut Menu, dish_lines: &mut Vec<String>) {
+    if let Some(offer) = menu.offers.last_mut() {
+        offer
+            .courses
+            .extend(dish_lines.drain(..).map(|line| parse_course(&line)));
+    }
+}
+
+fn parse_course(line: &str) -> Course {
+    lazy_static! {
+        static ref OR: Regex = Regex::new(r",? vai ").unwrap();
+    }
+    let mut options: Vec<Dish> = vec![];
+    for option in OR.split(line) {
+        let option = option.trim().trim_end_matches(',');
+        // An option without a kind of its own is another one of the previous kind, as in
+        // "🍲frikadeļu zupa vai dārzeņu \"Minestrone\"".
+        let kind = DishKind::parse(option)
+            .or_else(|| options.last().map(|dish| dish.kind))
+            .unwrap_or(DishKind::Main);
+        let name = option.trim_start_matches(|c: char| !c.is_alphanumeric() && c != '"');
+        if !name.is_empty() {
+            options.push(Dish {
+                kind,
+                name: String::from(name),
+            });
+        }
+    }
+    Course { options }
+}
+
+// Menus are posted around their day, so one for a month far from the one the post was published
+// in belongs to the neighbouring year, like a menu published on 31 December for 2 January.
+fn menu_year(published: u64, month: u32) -> i32 {
+    let (year, published_month) = year_month(published);
+    if month + 6 < published_month {
+        year + 1
+    } else if published_month + 6 < month {
+        year - 1
+    } else {
+        year
+    }
+}
+
+// Year and month of a unix timestamp in UTC, from the days since 1 March of year 0 in 400 year
+// cycles.
+fn year_month(timestamp: u64) -> (i32, u32) {
+    let days = (timestamp / (24 * 60 * 60)) as i64 + 719_468;
+    let day_of_cycle = days % 146_097;
+    let year_of_cycle =
+        (day_of_cycle - day_of_cycle / 1460 + day_of_cycle / 36_524 - day_of_cycle / 146_096) / 365;
+    let day_of_year =
+        day_of_cycle - (365 * year_of_cycle + year_of_cycle / 4 - year_of_cycle / 100);
+    // Months counted from March, so that the leap day ends the year.
+    let month = (5 * day_of_year + 2) / 153;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = days / 146_097 * 400 + year_of_cycle + if month <= 2 { 1 } else { 0 };
+    (year as i32, month as u32)
+}
+
+// Prices are written like "3,90€".
+fn parse_price(text: &str) -> Option<u32> {
+    let text = text.trim().trim_end_matches('€').trim();
+    let mut parts = text.splitn(2, [',', '.']);
+    let euros: u32 = parts.next()?.parse().ok()?;
+    let cents: u32 = match parts.next() {
+        Some(cents) if cents.len() == 2 => cents.parse().ok()?,
+        Some(_) => return None,
+        None => 0,
+    };
+    Some(euros * 100 + cents)
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, server_url};
+
+    use super::*;
+    use crate::sources::facebook::FacebookSource;
+    use crate::sources::PostSource;
+
+    const MENU: &str = "Pusdienu piedāvājums 6. februārī.  \n Dienas piedāvājums pieejams 11:00-16:00\n\n Mazais pusdienu piedāvājums:  \n🍗marinēts vistas giross vai 🐖c/g gulašs, vai   \n🌽pupiņu mikss ar sezamu  \n🥒 dienas salāti  \n🍷 dzērveņu dzēriens   \n💸 3,90€\n\n Lielais pusdienu piedāvājums:  \n🍲skābeņu zupa vai sēņu zupa, vai 🍰 dienas deserts  \n🍗marinēts vistas giross vai 🐖c/g gulašs, vai   \n🌽pupiņu mikss ar sezamu  \n🥒 dienas salāti  \n🍷 dzērveņu dzēriens   \n💸 4,60€\n\n Labu apetīti!\n\nSkatīt vairāk";
+
+    fn dish(kind: DishKind, name: &str) -> Dish {
+        Dish {
+            kind,
+            name: String::from(name),
+        }
+    }
+
+    fn course(options: Vec<Dish>) -> Course {
+        Course { options }
+    }
+
+    fn mains() -> Course {
+        course(vec![
+            dish(DishKind::Main, "marinēts vistas giross"),
+            dish(DishKind::Main, "c/g gulašs"),
+            dish(DishKind::Main, "pupiņu mikss ar sezamu"),
+        ])
+    }
+
+    fn sides() -> Vec<Course> {
+        vec![
+            course(vec![dish(DishKind::Salad, "dienas salāti")]),
+            course(vec![dish(DishKind::Drink, "dzērveņu dzēriens")]),
+        ]
+    }
+
+    #[test]
+    fn parses_menu() {
+        let menu = Menu::parse(MENU, None).unwrap();
+        let mut small = vec![mains()];
+        small.extend(sides());
+        let mut large = vec![
+            course(vec![
+                dish(DishKind::Soup, "skābeņu zupa"),
+                dish(DishKind::Soup, "sēņu zupa"),
+                dish(DishKind::Dessert, "dienas deserts"),
+            ]),
+            mains(),
+        ];
+        large.extend(sides());
+
+        assert_eq!(
+            menu,
+            Menu {
+                day: 6,
+                month: 2,
+                year: None,
+                hours: Some(ServingHours {
+                    from: Time {
+                        hour: 11,
+                        minute: 0
+                    },
+                    to: Time {
+                        hour: 16,
+                        minute: 0
+                    },
+                }),
+                notices: vec![],
+                offers: vec![
+                    Offer {
+                        tier: Tier::Small,
+                        courses: small,
+                        price_cents: Some(390),
+                    },
+                    Offer {
+                        tier: Tier::Large,
+                        courses: large,
+                        price_cents: Some(460),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn parses_notices() {
+        let text = "Pusdienu piedāvājums 23. janvārī.  \n Dienas piedāvājums pieejams 11:00-16:00\n\n !!!!!! Klīversala būs slēgta no 11:30 - 13:30 !!!!!!\n\n\n\n Mazais pusdienu piedāvājums:  \n🍗v/g saldskābā mērcē vai 🐖sautēta c/g ar dārzeņiem, vai 🌽pasta ar dārzeņiem  \n💸 3,75€";
+        let menu = Menu::parse(text, None).unwrap();
+        assert_eq!(menu.month, 1);
+        assert_eq!(menu.notices, vec!["Klīversala būs slēgta no 11:30 - 13:30"]);
+        assert_eq!(
+            menu.hours.unwrap().to,
+            Time {
+                hour: 16,
+                minute: 0
+            }
+        );
+        assert_eq!(menu.offers[0].price_cents, Some(375));
+        assert_eq!(menu.offers[0].courses[0].options.len(), 3);
+    }
+
+    #[test]
+    fn options_without_emoji_take_kind_from_name_or_previous_option() {
+        let course =
+            parse_course("🍲\"Soļanka\" vai dārzeņu \"Minestrone\", vai 🍰 dienas deserts");
+        assert_eq!(
+            course.options,
+            vec![
+                dish(DishKind::Soup, "\"Soļanka\""),
+                dish(DishKind::Soup, "dārzeņu \"Minestrone\""),
+                dish(DishKind::Dessert, "dienas deserts"),
+            ]
+        );
+    }
+
+    #[test]
+    fn takes_year_from_publishing_time() {
+        let text = |day: &str| {
+            format!(
+                "Pusdienu piedāvājums {}\n Mazais pusdienu piedāvājums:\n🍗vistas giross",
+                day
+            )
+        };
+        // Published on 6 February 2020, then on 31 December 2019 at 22:00 UTC.
+        let menu = Menu::parse(&text("7. februārī."), Some(1_580_965_200)).unwrap();
+        assert_eq!(menu.year, Some(2020));
+        let menu = Menu::parse(&text("2. janvārī."), Some(1_577_829_600)).unwrap();
+        assert_eq!(menu.year, Some(2020));
+        let menu = Menu::parse(&text("31. decembrī."), Some(1_577_829_600)).unwrap();
+        assert_eq!(menu.year, Some(2019));
+        // Published on 1 January 2020, a late post about the last menu of 2019.
+        let menu = Menu::parse(&text("30. decembrī."), Some(1_577_880_000)).unwrap();
+        assert_eq!(menu.year, Some(2019));
+    }
+
+    #[test]
+    fn reads_year_and_month_of_timestamps() {
+        assert_eq!(year_month(0), (1970, 1));
+        assert_eq!(year_month(951_782_400), (2000, 2));
+        assert_eq!(year_month(1_582_934_400), (2020, 2));
+        assert_eq!(year_month(1_583_020_800), (2020, 3));
+        assert_eq!(year_month(1_609_459_199), (2020, 12));
+    }
+
+    #[test]
+    fn parses_prices() {
+        assert_eq!(parse_price(" 3,90€"), Some(390));
+        assert_eq!(parse_price("4.6 €"), None);
+        assert_eq!(parse_price("5€"), Some(500));
+        assert_eq!(parse_price("bez maksas"), None);
+    }
+
+    #[test]
+    fn other_posts_are_not_menus() {
+        assert_eq!(
+            Menu::parse("Nāc un piedalies arī Tu, jau no 01.02.2020! 🥘🍴☕", None),
+            None
+        );
+        assert_eq!(Menu::parse("", None), None);
+        assert_eq!(Menu::parse("Pusdienu piedāvājums 7. februārī.", None), None);
+        assert_eq!(
+            Menu::parse(
+                "Pusdienu piedāvājums 7. brīvdienā.\n Mazais pusdienu piedāvājums:",
+                None
+            ),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_every_menu_of_mock_response() {
+        let _m = mock("GET", "/menu/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body_from_file("_mock_response")
+            .create();
+
+        let source =
+            FacebookSource::new(&format!("{}/menu/kantineKliversala/posts/", server_url()));
+        let posts = source.fetch_posts(&[]).await.unwrap();
+        let menus: Vec<Option<Menu>> = posts
+            .iter()
+            .map(|post| Menu::parse(&post.text, post.published))
+            .collect();
+
+        assert_eq!(menus.len(), 19);
+        assert_eq!(menus[5], None);
+        for (post, menu) in posts.iter().zip(&menus) {
+            if post.id == "2465890140339822" {
+                continue;
+            }
+            let menu = menu
+                .as_ref()
+                .unwrap_or_else(|| panic!("post {} is not a menu", post.id));
+            assert_eq!(menu.offers.len(), 2, "post {}", post.id);
+            assert_eq!(menu.offers[0].tier, Tier::Small);
+            assert_eq!(menu.offers[0].courses.len(), 3, "post {}", post.id);
+            assert_eq!(menu.offers[1].tier, Tier::Large);
+            assert_eq!(menu.offers[1].courses.len(), 4, "post {}", post.id);
+            assert!(menu.offers[0].courses[0]
+                .options
+                .iter()
+                .all(|dish| dish.kind == DishKind::Main));
+            assert!(menu.offers[1].price_cents > menu.offers[0].price_cents);
+        }
+
+        let first = menus[0].as_ref().unwrap();
+        assert_eq!((first.day, first.month), (7, 2));
+        assert_eq!(first.offers[1].price_cents, Some(460));
+        let last = menus[18].as_ref().unwrap();
+        assert_eq!((last.day, last.month), (15, 1));
+        assert_eq!(last.offers[0].price_cents, Some(375));
+        assert_eq!(
+            menus[7].as_ref().unwrap().notices,
+            vec!["Klīversalas darba laiks šodien no 9:00 līdz 15:00"]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::error::Error;
+use crate::sources::PostKind;
+
+#[async_trait]
+pub trait Publisher {
+    async fn publish_text(&self, text: &str) -> Result<String, Error>;
+    async fn publish_image(&self, image_url: &str) -> Result<String, Error>;
+    /// Publishes the images as a single album, with the caption attached to the first one.
+    async fn publish_album(
+        &self,
+        image_urls: &[&str],
+        caption: Option<&str>,
+    ) -> Result<Vec<String>, Error>;
+    async fn edit_text(&self, message_id: &str, text: &str) -> Result<(), Error>;
+    async fn edit_caption(&self, message_id: &str, caption: &str) -> Result<(), Error>;
+    async fn replace_image(
+        &self,
+        message_id: &str,
+        image_url: &str,
+        caption: Option<&str>,
+    ) -> Result<(), Error>;
+    async fn delete(&self, message_id: &str) -> Result<(), Error>;
+}
+
+/// Picks the publisher, and with it the chat, that posts of a kind are forwarded to.
+pub trait Router {
+    type Publisher: Publisher;
+
+    /// Returns `None` for kinds that are not forwarded at all.
+    fn route(&self, kind: PostKind) -> Option<&Self::Publisher>;
+}
+
+// A single publisher forwards every post.
+impl<P: Publisher> Router for P {
+    type Publisher = P;
+
+    fn route(&self, _kind: PostKind) -> Option<&P> {
+        Some(self)
+    }
+}
+
+/// Forwards each kind of post to the chat of its own publisher.
+pub struct Routes<P> {
+    publishers: Vec<P>,
+    routes: HashMap<PostKind, usize>,
+}
+
+impl<P> Routes<P> {
+    pub fn new() -> Routes<P> {
+        Routes {
+            publishers: vec![],
+            routes: HashMap::new(),
+        }
+    }
+
+    /// Forwards posts of `kinds` through `publisher`, which replaces any earlier route of them.
+    pub fn with(mut self, kinds: &[PostKind], publisher: P) -> Routes<P> {
+        for kind in kinds {
+            self.routes.insert(*kind, self.publishers.len());
+        }
+        self.publishers.push(publisher);
+        self
+    }
+}
+
+impl<P> Default for Routes<P> {
+    fn default() -> Routes<P> {
+        Routes::new()
+    }
+}
+
+impl<P: Publisher> Router for Routes<P> {
+    type Publisher = P;
+
+    fn route(&self, kind: PostKind) -> Option<&P> {
+        self.routes.get(&kind).map(|index| &self.publishers[*index])
+    }
+}
@@ -0,0 +1,110 @@
+use crate::menu::Menu;
+use crate::sources::PostKind;
+
+// Words that mark a post about a discount, a giveaway or a contest.
+const PROMOTION_WORDS: &[&str] = &[
+    "akcij",
+    "atlaid",
+    "%",
+    "konkurs",
+    "dāvan",
+    "bezmaksas",
+    "laimē",
+];
+// Words that, along with "nedēļa" (week), mark the menu of a whole week.
+const WEEKLY_MENU_WORDS: &[&str] = &["piedāvājum", "ēdienkart", "menu"];
+
+/// Tells what a post is about from its text.
+///
+/// A post that parses as a `Menu`, or at least starts like one, is a daily menu. Anything that is
+/// neither a menu nor a promotion is an announcement.
+pub fn classify(text: &str) -> PostKind {
+    if Menu::parse(text, None).is_some() {
+        return PostKind::DailyMenu;
+    }
+    let text = text.trim().to_lowercase();
+    if text.contains("nedēļ") && WEEKLY_MENU_WORDS.iter().any(|word| text.contains(word)) {
+        return PostKind::WeeklyMenu;
+    }
+    if text.starts_with("pusdienu piedāvājums") {
+        return PostKind::DailyMenu;
+    }
+    if PROMOTION_WORDS.iter().any(|word| text.contains(word)) {
+        return PostKind::Promotion;
+    }
+    PostKind::Announcement
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, server_url};
+
+    use super::*;
+    use crate::sources::facebook::FacebookSource;
+    use crate::sources::PostSource;
+
+    #[test]
+    fn classifies_daily_menus() {
+        assert_eq!(
+            classify("Pusdienu piedāvājums 7. februārī.  \n Mazais pusdienu piedāvājums:  \n🍗v/g plovs  \n💸 3,90€"),
+            PostKind::DailyMenu
+        );
+        assert_eq!(
+            classify("Pusdienu piedāvājums šodien: zupa un plovs"),
+            PostKind::DailyMenu
+        );
+    }
+
+    #[test]
+    fn classifies_weekly_menus() {
+        assert_eq!(
+            classify("Nedēļas piedāvājums no 3. līdz 7. februārim  \n🍲 zupa"),
+            PostKind::WeeklyMenu
+        );
+        assert_eq!(classify("Ēdienkarte visai nedēļai"), PostKind::WeeklyMenu);
+    }
+
+    #[test]
+    fn classifies_promotions() {
+        assert_eq!(
+            classify("Tikai šodien -20% atlaide visiem desertiem!"),
+            PostKind::Promotion
+        );
+        assert_eq!(
+            classify("Piedalies konkursā un laimē pusdienas divatā!"),
+            PostKind::Promotion
+        );
+    }
+
+    #[test]
+    fn classifies_announcements() {
+        assert_eq!(
+            classify("Nāc un piedalies arī Tu, jau no 01.02.2020! 🥘🍴☕"),
+            PostKind::Announcement
+        );
+        assert_eq!(classify(""), PostKind::Announcement);
+    }
+
+    #[tokio::test]
+    async fn classifies_mock_response() {
+        let _m = mock("GET", "/classifier/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body_from_file("_mock_response")
+            .create();
+
+        let source = FacebookSource::new(&format!(
+            "{}/classifier/kantineKliversala/posts/",
+            server_url()
+        ));
+        let posts = source.fetch_posts(&[]).await.unwrap();
+
+        for post in &posts {
+            let expected = if post.id == "2465890140339822" {
+                PostKind::Announcement
+            } else {
+                PostKind::DailyMenu
+            };
+            assert_eq!(post.kind, expected, "post {}", post.id);
+        }
+    }
+}
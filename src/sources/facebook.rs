@@ -1,14 +1,17 @@
-use std::error::Error;
+use std::iter;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use html2md::parse_html;
-use log::{debug, info};
+use log::{debug, info, warn};
 use regex::Regex;
-use reqwest::Client;
-use scraper::{Html, Selector};
+use reqwest::{Client, Url};
+use scraper::{ElementRef, Html, Selector};
 
 use async_trait::async_trait;
 
-use crate::sources::{Image, Post, PostSource};
+use crate::error::Error;
+use crate::sources::classifier::classify;
+use crate::sources::{image_key, Image, Post, PostSource};
 
 const POSTS_SELECTOR: &str = "#pagelet_timeline_main_column > div:first-of-type > div:nth-child(2) > div:first-of-type > div";
 const IMAGE_CONTAINER_SELECTOR: &str = concat!(
@@ -18,53 +21,254 @@ const IMAGE_CONTAINER_SELECTOR: &str = concat!(
 const ID_SELECTOR: &str = r#"div[data-testid="story-subtitle"]"#;
 const TEXT_SELECTOR: &str = r#"div[data-testid="post_message"] > *:first-child"#;
 const IMAGE_SELECTOR: &str = "img";
+const TIMESTAMP_SELECTOR: &str = r#"div[data-testid="story-subtitle"] abbr[data-utime]"#;
+const PERMALINK_SELECTOR: &str = r#"div[data-testid="story-subtitle"] a[href]"#;
+const AUTHOR_SELECTOR: &str = "h5 a";
+const LINK_SELECTOR: &str = r#"div[data-testid="post_message"] a[href]"#;
+// The "..." and "See more" shown in place of the rest of the text until a post is expanded.
+const HIDDEN_SELECTOR: &str = ".text_exposed_hide";
+const FULL_POST_IMAGE_SELECTOR: &str =
+    r#"div[data-testid="post_message"] ~ div a[rel="theater"] img"#;
+const PHOTO_SELECTOR: &str = r#"meta[property="og:image"]"#;
+
+const NAME: &str = "facebook";
+// How long after publishing the page of a truncated post is read again on every run, as edits in
+// the hidden part of its text do not show on the timeline.
+const REFETCH_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
 
 pub struct FacebookSource {
     url: String,
 }
 
+impl FacebookSource {
+    pub fn new(url: &str) -> FacebookSource {
+        FacebookSource {
+            url: String::from(url),
+        }
+    }
+}
+
 #[async_trait]
 impl PostSource for FacebookSource {
-    type Source = FacebookSource;
+    fn name(&self) -> &str {
+        NAME
+    }
 
-    fn new(url: &str) -> FacebookSource {
-        FacebookSource {
-            url: String::from(url),
+    async fn fetch_posts(&self, known: &[Post]) -> Result<Vec<Post>, Error> {
+        fetch_posts(&self.url, known).await
+    }
+
+    // Posts the timeline could not be parsed for are missing from it as well, only the page of
+    // the post itself tells whether it is gone.
+    async fn is_deleted(&self, post: &Post) -> Result<bool, Error> {
+        let permalink = match &post.permalink {
+            Some(permalink) => permalink,
+            None => return Ok(false),
+        };
+        let resp = Client::new()
+            .get(permalink)
+            .header("user-agent", "rusty")
+            .send()
+            .await?;
+        match resp.status().as_u16() {
+            404 | 410 => Ok(true),
+            _ if resp.status().is_success() => Ok(false),
+            status => Err(Error::SourceStatus {
+                status,
+                body: resp.text().await?,
+            }),
         }
     }
-    async fn fetch_posts(&self) -> Result<Vec<Post>, Box<dyn Error>> {
-        fetch_posts(&self.url).await
+}
+
+async fn fetch_posts(url: &str, known: &[Post]) -> Result<Vec<Post>, Error> {
+    let client = Client::new();
+    let (page_url, body) = fetch_page(&client, url).await?;
+    let mut result: Vec<Post> = Vec::new();
+
+    // Pages are parsed before fetching anything else, as a parsed document can not be held
+    // across an await.
+    for scraped in parse_posts(&page_url, &body)? {
+        let ScrapedPost {
+            mut post,
+            mut images,
+            full_post_url,
+        } = scraped;
+        let known_post = known.iter().find(|known| known.id == post.id);
+        if let (Some(_), Some(known_post)) = (&full_post_url, known_post) {
+            if !is_recent(&post) && is_known_full_post(&post, &images, known_post) {
+                debug!("post {} is unchanged, keeping its full text", post.id);
+                post.kind = known_post.kind;
+                post.text = known_post.text.clone();
+                post.images = known_post
+                    .images
+                    .iter()
+                    .map(|image| Image {
+                        tg_id: None,
+                        ..image.clone()
+                    })
+                    .collect();
+                result.push(post);
+                continue;
+            }
+        }
+        if let Some(full_post_url) = full_post_url {
+            info!("post {} is truncated, fetching {}", post.id, full_post_url);
+            // Without the full text the post is left as it is until a later run gets it.
+            post.truncated = true;
+            match fetch_page(&client, full_post_url.as_str()).await {
+                Ok((full_post_url, body)) => {
+                    match parse_full_post(&full_post_url, &body, &post.id) {
+                        Ok(Some((text, full_images))) => {
+                            post.kind = classify(&text);
+                            post.text = text;
+                            post.truncated = false;
+                            if !full_images.is_empty() {
+                                images = full_images;
+                            }
+                        }
+                        Ok(None) => warn!("full text of post {} not found", post.id),
+                        Err(error) => warn!("failed to parse post {}: {}", post.id, error),
+                    }
+                }
+                Err(error) => warn!("failed to fetch full text of post {}: {}", post.id, error),
+            }
+        }
+        post.images = resolve_images(&client, images, known_post).await;
+        result.push(post);
     }
+
+    Ok(result)
 }
 
-async fn fetch_posts(url: &str) -> Result<Vec<Post>, Box<dyn Error>> {
-    let resp = Client::new()
-        .get(url)
-        .header("user-agent", "rusty")
-        .send()
-        .await?;
+async fn fetch_page(client: &Client, url: &str) -> Result<(Url, String), Error> {
+    let resp = client.get(url).header("user-agent", "rusty").send().await?;
 
     if !resp.status().is_success() {
-        return Err(resp.text().await?.into());
+        return Err(Error::SourceStatus {
+            status: resp.status().as_u16(),
+            body: resp.text().await?,
+        });
     }
 
-    let res_text = resp.text().await?;
-    let mut result: Vec<Post> = Vec::new();
+    let page_url = resp.url().clone();
+    Ok((page_url, resp.text().await?))
+}
 
-    let document = Html::parse_document(&res_text);
+// Timeline images are resized thumbnails. The full size of most is given next to them, the others
+// are resolved on the page of the photo, unless it is known from before, and keep their thumbnail
+// when that fails.
+async fn resolve_images(
+    client: &Client,
+    images: Vec<ScrapedImage>,
+    known_post: Option<&Post>,
+) -> Vec<Image> {
+    let mut result = Vec::new();
+    for (image, photo_url) in images {
+        let photo_url = match photo_url {
+            Some(photo_url) => photo_url,
+            None => {
+                result.push(image);
+                continue;
+            }
+        };
+        let known_image = known_post
+            .and_then(|known_post| known_image(&image, known_post))
+            .filter(|known_image| known_image.thumbnail_url.is_some());
+        if let Some(known_image) = known_image {
+            result.push(Image {
+                tg_id: None,
+                ..known_image.clone()
+            });
+            continue;
+        }
+        match fetch_page(client, photo_url.as_str()).await {
+            Ok((_, body)) => match parse_photo(&body) {
+                Some(url) => result.push(Image::full_size(&url, &image.url)),
+                None => {
+                    warn!("full size of image {} not found", image.url);
+                    result.push(image);
+                }
+            },
+            Err(error) => {
+                warn!(
+                    "failed to fetch full size of image {}: {}",
+                    image.url, error
+                );
+                result.push(image);
+            }
+        }
+    }
+    result
+}
+
+// A truncated post has not changed while the stored one still starts with the text the timeline
+// shows and has each of its images. Edits further in the text are only noticed along with those
+// once the post is older than `REFETCH_PERIOD`.
+// The stored text must be longer, it may itself be truncated if it was stored by an older version.
+fn is_known_full_post(post: &Post, images: &[ScrapedImage], known_post: &Post) -> bool {
+    known_post.text.len() > post.text.len()
+        && known_post.text.starts_with(&post.text)
+        && images
+            .iter()
+            .all(|(image, _)| known_image(image, known_post).is_some())
+}
+
+// Posts without a timestamp count as recent, there is no telling how old they are.
+fn is_recent(post: &Post) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    match post.published {
+        Some(published) => Duration::from_secs(published) + REFETCH_PERIOD > now,
+        None => true,
+    }
+}
+
+// The stored image of a picture, found by its full size or by the thumbnail on the timeline.
+fn known_image<'a>(image: &Image, known_post: &'a Post) -> Option<&'a Image> {
+    known_post.images.iter().find(|known_image| {
+        known_image.key == image.key
+            || known_image.thumbnail_url.as_deref().map(image_key).as_ref() == Some(&image.key)
+    })
+}
+
+// An image as found on a page, with the page of the photo when its full size is not known yet.
+type ScrapedImage = (Image, Option<Url>);
+
+// A post as found on the timeline, before the pages it links to are fetched.
+struct ScrapedPost {
+    /// Without images, those are resolved to their full size first.
+    post: Post,
+    images: Vec<ScrapedImage>,
+    /// The page of the post, when the timeline only shows the beginning of its text.
+    full_post_url: Option<Url>,
+}
+
+fn parse_posts(page_url: &Url, res_text: &str) -> Result<Vec<ScrapedPost>, Error> {
+    let mut result = Vec::new();
+
+    let document = Html::parse_document(res_text);
     let posts_selector = Selector::parse(POSTS_SELECTOR).unwrap();
     let id_selector = Selector::parse(ID_SELECTOR).unwrap();
-    let text_selector = Selector::parse(TEXT_SELECTOR).unwrap();
     let image_container_selector = Selector::parse(IMAGE_CONTAINER_SELECTOR).unwrap();
     let image_selector = Selector::parse(IMAGE_SELECTOR).unwrap();
+    let timestamp_selector = Selector::parse(TIMESTAMP_SELECTOR).unwrap();
+    let permalink_selector = Selector::parse(PERMALINK_SELECTOR).unwrap();
+    let author_selector = Selector::parse(AUTHOR_SELECTOR).unwrap();
+    let link_selector = Selector::parse(LINK_SELECTOR).unwrap();
+    let text_selector = Selector::parse(TEXT_SELECTOR).unwrap();
 
     for post in document.select(&posts_selector) {
         let mut post_id = "";
         for id_element in post.select(&id_selector) {
-            post_id = id_element.value().id().unwrap();
+            post_id = id_element
+                .value()
+                .id()
+                .ok_or_else(|| Error::SourceParse(String::from("post subtitle without id")))?;
         }
 
-        if post_id == "" {
+        if post_id.is_empty() {
             continue;
         }
 
@@ -75,61 +279,154 @@ async fn fetch_posts(url: &str) -> Result<Vec<Post>, Box<dyn Error>> {
         }
         let post_id = post_id[1];
 
-        let mut text_parts: Vec<String> = Vec::new();
-
-        for text in post.select(&text_selector) {
-            text_parts.push(text.inner_html());
-        }
-        let text = text_parts.concat();
-        let parsed_text = parse_html(&text);
-
-        let mut images: Vec<Image> = Vec::new();
+        let published = post
+            .select(&timestamp_selector)
+            .next()
+            .and_then(|abbr| abbr.value().attr("data-utime"))
+            .and_then(|utime| utime.parse().ok());
+
+        // The timestamp links to the post, the links after it are "#" placeholders.
+        let permalink = post
+            .select(&permalink_selector)
+            .filter_map(|link| link.value().attr("href"))
+            .find(|href| !href.starts_with('#'))
+            .and_then(|href| page_url.join(href).ok())
+            .map(|permalink| permalink.to_string());
+
+        let author = post
+            .select(&author_selector)
+            .next()
+            .map(|link| link.text().collect::<String>().trim().to_string())
+            .filter(|author| !author.is_empty());
+
+        // Long posts end with a "See more" link to the post instead of the rest of their text.
+        let full_post_url = post
+            .select(&link_selector)
+            .filter_map(|link| link.value().attr("href"))
+            .find(|href| href.contains(&format!("/posts/{}", post_id)))
+            .and_then(|href| page_url.join(href).ok());
+
+        let parsed_text = parse_text(post.select(&text_selector), post_id);
+
+        let mut images = Vec::new();
         for img_container in post.select(&image_container_selector) {
             for img_element in img_container.select(&image_selector) {
-                let img_src = img_element.value().attr("src").unwrap();
-                info!("img src: {}", img_src);
-                let image = Image {
-                    url: String::from(img_src),
-                    tg_id: None,
-                };
-                images.push(image);
+                images.push(parse_image(page_url, img_element, post_id)?);
             }
         }
 
-        debug!("parsed html into markdown: {}", parsed_text);
-
-        let parsed_text = parsed_text
-            .replace("\\-", "-")
-            .replace("...", "")
-            .replace(
-                format!("[See more](/kantineKliversala/posts/{})", post_id).as_str(),
-                "",
-            )
-            .replace(
-                format!("[See More](/kantineKliversala/posts/{})", post_id).as_str(),
-                "",
-            );
-
-        let parsed_text = remove_markdown_links(&parsed_text);
-
         let post = Post {
-            id: format!("{}", post_id).replace("\"", ""),
-            text: parsed_text,
-            images,
-            tg_id: None,
+            published,
+            source: String::from(NAME),
+            permalink,
+            author,
+            ..Post::new(&post_id.replace("\"", ""), &parsed_text, vec![])
         };
 
-        result.push(post);
+        result.push(ScrapedPost {
+            post,
+            images,
+            full_post_url,
+        });
     }
 
     Ok(result)
 }
 
+// Returns the text and images of the post on its own page.
+fn parse_full_post(
+    page_url: &Url,
+    res_text: &str,
+    post_id: &str,
+) -> Result<Option<(String, Vec<ScrapedImage>)>, Error> {
+    let document = Html::parse_document(res_text);
+    let text_selector = Selector::parse(TEXT_SELECTOR).unwrap();
+    let full_image_selector = Selector::parse(FULL_POST_IMAGE_SELECTOR).unwrap();
+
+    let text = match document.select(&text_selector).next() {
+        Some(text) => parse_text(iter::once(text), post_id),
+        None => return Ok(None),
+    };
+    let images = document
+        .select(&full_image_selector)
+        .map(|img_element| parse_image(page_url, img_element, post_id))
+        .collect::<Result<_, _>>()?;
+    Ok(Some((text, images)))
+}
+
+fn parse_image(
+    page_url: &Url,
+    img_element: ElementRef,
+    post_id: &str,
+) -> Result<ScrapedImage, Error> {
+    let img_src = img_element
+        .value()
+        .attr("src")
+        .ok_or_else(|| Error::SourceParse(format!("image without src in post {}", post_id)))?;
+    info!("img src: {}", img_src);
+
+    let photo_link = img_element
+        .ancestors()
+        .filter_map(ElementRef::wrap)
+        .find(|element| element.value().name() == "a");
+    if let Some(full_url) = photo_link.and_then(|link| link.value().attr("data-ploi")) {
+        return Ok((Image::full_size(full_url, img_src), None));
+    }
+    let photo_url = photo_link
+        .and_then(|link| link.value().attr("href"))
+        .filter(|href| !href.starts_with('#'))
+        .and_then(|href| page_url.join(href).ok());
+    Ok((Image::new(img_src), photo_url))
+}
+
+// Returns the full size of the picture on the page of a photo.
+fn parse_photo(res_text: &str) -> Option<String> {
+    let document = Html::parse_document(res_text);
+    let photo_selector = Selector::parse(PHOTO_SELECTOR).unwrap();
+    document
+        .select(&photo_selector)
+        .next()
+        .and_then(|meta| meta.value().attr("content"))
+        .map(String::from)
+}
+
+// Converts the message of a post to markdown, leaving out what the timeline hides until the post
+// is expanded.
+fn parse_text<'a>(texts: impl Iterator<Item = ElementRef<'a>>, post_id: &str) -> String {
+    let hidden_selector = Selector::parse(HIDDEN_SELECTOR).unwrap();
+
+    let mut text_parts: Vec<String> = Vec::new();
+    for text in texts {
+        let mut html = text.inner_html();
+        for hidden in text.select(&hidden_selector) {
+            html = html.replace(&hidden.html(), "");
+        }
+        text_parts.push(html);
+    }
+    let text = text_parts.concat();
+    let parsed_text = parse_html(&text);
+
+    debug!("parsed html into markdown: {}", parsed_text);
+
+    let parsed_text = parsed_text
+        .replace("\\-", "-")
+        .replace(
+            format!("[See more](/kantineKliversala/posts/{})", post_id).as_str(),
+            "",
+        )
+        .replace(
+            format!("[See More](/kantineKliversala/posts/{})", post_id).as_str(),
+            "",
+        );
+
+    String::from(remove_markdown_links(&parsed_text).trim_end())
+}
+
 fn remove_markdown_links(text: &str) -> String {
     lazy_static! {
         static ref RE: Regex = Regex::new(r"\[(.*?)\]\(.*?\)").unwrap();
     }
-    return String::from(RE.replace_all(text, "$1"));
+    String::from(RE.replace_all(text, "$1"))
 }
 
 #[cfg(test)]
@@ -146,20 +443,40 @@ mod tests {
             .with_body_from_file("_mock_response")
             .create();
 
-        let result = fetch_posts(format!("{}/pg/kantineKliversala/posts/", url).as_str())
+        let result = fetch_posts(format!("{}/pg/kantineKliversala/posts/", url).as_str(), &[])
             .await
             .unwrap();
         assert_eq!(result.len(), 19);
         assert_eq!(result[0].id, "2471140943148075");
-        assert_eq!(result[0].text, "Pusdienu piedāvājums 7. februārī.  \n Dienas piedāvājums pieejams 11:00-16:00\n\n Mazais pusdienu piedāvājums:  \n🍗v/g saldakābā mērcē vai 🥘makaroni \"Jūrnieku gaumē\", vai 🌽kuskuss ar dārzeņiem  \n🥒 dienas salāti  \n🍷 dzērveņu dzēriens   \n💸 3,90€\n\n Lielais pusdienu piedāvājums:  \n🍲frikadeļu zupa vai dārzeņu krēmzupa, vai 🍰 dienas deserts  \n🍗v/g saldskābā mērcē vai 🥘makaroni \"Jūrnieku gaumē\", vai 🌽kuskuss ar dārzeņiem  \n🥒 dienas salāti  \n🍷 dzērveņu dzēriens   \n💸 4,60€\n\n Labu apetīti!\n\nSkatīt vairāk");
+        assert_eq!(result[0].text, "Pusdienu piedāvājums 7. februārī.  \n Dienas piedāvājums pieejams 11:00-16:00\n\n Mazais pusdienu piedāvājums:  \n🍗v/g saldakābā mērcē vai 🥘makaroni \"Jūrnieku gaumē\", vai 🌽kuskuss ar dārzeņiem  \n🥒 dienas salāti  \n🍷 dzērveņu dzēriens   \n💸 3,90€\n\n Lielais pusdienu piedāvājums:  \n🍲frikadeļu zupa vai dārzeņu krēmzupa, vai 🍰 dienas deserts  \n🍗v/g saldskābā mērcē vai 🥘makaroni \"Jūrnieku gaumē\", vai 🌽kuskuss ar dārzeņiem  \n🥒 dienas salāti  \n🍷 dzērveņu dzēriens   \n💸 4,60€\n\n Labu apetīti!");
         assert_eq!(result[0].images.len(), 0);
+        assert_eq!(result[0].published, Some(1581059445));
+        assert_eq!(result[5].published, Some(1580465130));
+        assert_eq!(result[18].published, Some(1579073415));
+        assert_eq!(result[0].source, "facebook");
+        assert_eq!(
+            result[0].permalink,
+            Some(format!("{}/kantineKliversala/posts/2471140943148075", url))
+        );
+        assert_eq!(
+            result[5].permalink,
+            Some(format!(
+                "{}/kantineKliversala/photos/a.1637345399860971/2465890097006493/?type=3",
+                url
+            ))
+        );
+        assert_eq!(
+            result[0].author,
+            Some(String::from("Kantīne \"Klīversala\""))
+        );
 
         assert_eq!(result[5].id, "2465890140339822");
         assert_eq!(
             result[5].text,
             "Nāc un piedalies arī Tu, jau no 01.02.2020! 🥘🍴☕"
         );
-        assert_eq!(result[5].images[0].url, String::from("https://scontent.frix3-1.fna.fbcdn.net/v/t1.0-0/p526x296/84437983_2465890103673159_2752238738611372032_o.jpg?_nc_cat=106&_nc_ohc=YlgO1JJVbLQAX8aROMV&_nc_ht=scontent.frix3-1.fna&_nc_tp=6&oh=a9a1e00cf9bf5ce65254d36f7ef27590&oe=5EC77203"));
+        assert_eq!(result[5].images[0].url, String::from("https://scontent.frix3-1.fna.fbcdn.net/v/t1.0-9/84437983_2465890103673159_2752238738611372032_o.jpg?_nc_cat=106&_nc_ohc=YlgO1JJVbLQAX8aROMV&_nc_ht=scontent.frix3-1.fna&oh=7f3468bee7d91ab5eae3d5cf1449047e&oe=5ED20FDD"));
+        assert_eq!(result[5].images[0].thumbnail_url, Some(String::from("https://scontent.frix3-1.fna.fbcdn.net/v/t1.0-0/p526x296/84437983_2465890103673159_2752238738611372032_o.jpg?_nc_cat=106&_nc_ohc=YlgO1JJVbLQAX8aROMV&_nc_ht=scontent.frix3-1.fna&_nc_tp=6&oh=a9a1e00cf9bf5ce65254d36f7ef27590&oe=5EC77203")));
         _m.assert();
     }
 
@@ -171,7 +488,7 @@ mod tests {
             .with_body("<html><body><div>empty</div></body></html>")
             .create();
 
-        let result = fetch_posts(format!("{}/pg/kantineKliversala/posts/", url).as_str())
+        let result = fetch_posts(format!("{}/pg/kantineKliversala/posts/", url).as_str(), &[])
             .await
             .unwrap();
         assert_eq!(result.len(), 0);
@@ -186,7 +503,7 @@ mod tests {
             .with_body("something")
             .create();
 
-        let result = fetch_posts(format!("{}/pg/kantineKliversala/posts/", url).as_str())
+        let result = fetch_posts(format!("{}/pg/kantineKliversala/posts/", url).as_str(), &[])
             .await
             .unwrap();
         assert_eq!(result.len(), 0);
@@ -201,14 +518,389 @@ mod tests {
             .with_body("error")
             .create();
 
-        let result = fetch_posts(format!("{}/pg/kantineKliversala/posts/", url).as_str())
+        let result = fetch_posts(format!("{}/pg/kantineKliversala/posts/", url).as_str(), &[])
             .await
             .unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, "error");
+        match result {
+            Error::SourceStatus { status, body } => {
+                assert_eq!(status, 400);
+                assert_eq!(body, "error");
+            }
+            _ => panic!("unexpected error: {:?}", result),
+        }
         _m.assert();
     }
 
+    // A timeline page holding a single post with the given message and attached photos.
+    fn timeline(post_id: &str, message: &str, photos: &str) -> String {
+        format!(
+            r#"<html><body><div id="pagelet_timeline_main_column"><div><div></div><div><div><div>
+                <h5><a href="https://www.facebook.com/kantineKliversala/">Kantīne "Klīversala"</a></h5>
+                <div data-testid="story-subtitle" id="feed_subtitle_1609930535935791;{id};;9">
+                    <a href="/kantineKliversala/posts/{id}"><abbr data-utime="1581059445">1 h</abbr></a>
+                </div>
+                <div data-testid="post_message">{message}</div>
+                <div><div><div><div><div><div><div></div><div>{photos}</div></div></div></div></div></div>
+            </div></div></div></div></div></body></html>"#,
+            id = post_id,
+            message = message,
+            photos = photos
+        )
+    }
+
+    const FULL_POST: &str = r#"<html><body><div class="userContentWrapper">
+        <h5><a href="https://www.facebook.com/kantineKliversala/">Kantīne "Klīversala"</a></h5>
+        <div data-testid="post_message"><div>
+            <p>Pusdienu piedāvājums 7. februārī.</p>
+            <p>Lielais pusdienu piedāvājums: frikadeļu zupa</p>
+        </div></div>
+        <div>
+            <a rel="theater" data-ploi="https://example.com/1_o.jpg"><img src="https://example.com/1_n.jpg"></a>
+            <a rel="theater" data-ploi="https://example.com/2_o.jpg"><img src="https://example.com/2_n.jpg"></a>
+        </div>
+    </div></body></html>"#;
+
+    #[tokio::test]
+    async fn fetch_posts_follows_see_more() {
+        let url = &server_url();
+        let _timeline = mock("GET", "/full/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body(timeline(
+                "101",
+                r#"<p>Pusdienu piedāvājums 7. februārī. <a href="/kantineKliversala/posts/101">See more</a></p>"#,
+                "",
+            ))
+            .create();
+        let _post = mock("GET", "/kantineKliversala/posts/101")
+            .with_status(200)
+            .with_body(FULL_POST)
+            .create();
+
+        let result = fetch_posts(&format!("{}/full/kantineKliversala/posts/", url), &[])
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            result[0].text,
+            "Pusdienu piedāvājums 7. februārī.\n\nLielais pusdienu piedāvājums: frikadeļu zupa"
+        );
+        assert!(!result[0].truncated);
+        assert_eq!(
+            result[0].images,
+            vec![
+                Image::full_size("https://example.com/1_o.jpg", "https://example.com/1_n.jpg"),
+                Image::full_size("https://example.com/2_o.jpg", "https://example.com/2_n.jpg")
+            ]
+        );
+        _timeline.assert();
+        _post.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_marks_post_truncated_when_post_fails() {
+        let url = &server_url();
+        let _timeline = mock("GET", "/truncated/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body(timeline(
+                "102",
+                r#"<p>Pusdienu piedāvājums 7. februārī. <a href="/kantineKliversala/posts/102">See More</a></p>"#,
+                "",
+            ))
+            .create();
+        let _post = mock("GET", "/kantineKliversala/posts/102")
+            .with_status(500)
+            .with_body("error")
+            .create();
+
+        let result = fetch_posts(&format!("{}/truncated/kantineKliversala/posts/", url), &[])
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "Pusdienu piedāvājums 7. februārī.");
+        assert!(result[0].truncated);
+        _post.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_leaves_out_hidden_see_more() {
+        let url = &server_url();
+        let _timeline = mock("GET", "/expanded/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body(timeline(
+                "103",
+                r#"<div><p>Mazais pusdienu piedāvājums<span class="text_exposed_hide">...</span><span class="text_exposed_show">: dienas salāti</span></p>
+                <span class="text_exposed_hide"><a class="see_more_link" onclick="">Skatīt vairāk</a></span></div>"#,
+                "",
+            ))
+            .create();
+        let _post = mock("GET", "/kantineKliversala/posts/103")
+            .expect(0)
+            .create();
+
+        let result = fetch_posts(&format!("{}/expanded/kantineKliversala/posts/", url), &[])
+            .await
+            .unwrap();
+        assert_eq!(result[0].text, "Mazais pusdienu piedāvājums: dienas salāti");
+        _post.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_follows_photo_links() {
+        let url = &server_url();
+        let _timeline = mock("GET", "/photos/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body(timeline(
+                "104",
+                "<p>Nāc un piedalies arī Tu!</p>",
+                r#"<a rel="theater" href="/kantineKliversala/photos/a.1/201/"><img src="https://example.com/p526x296/201_n.jpg"></a>"#,
+            ))
+            .create();
+        let _photo = mock("GET", "/kantineKliversala/photos/a.1/201/")
+            .with_status(200)
+            .with_body(r#"<html><head><meta property="og:image" content="https://example.com/201_n.jpg"></head></html>"#)
+            .create();
+
+        let result = fetch_posts(&format!("{}/photos/kantineKliversala/posts/", url), &[])
+            .await
+            .unwrap();
+        assert_eq!(
+            result[0].images,
+            vec![Image::full_size(
+                "https://example.com/201_n.jpg",
+                "https://example.com/p526x296/201_n.jpg"
+            )]
+        );
+        _photo.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_keeps_thumbnail_when_photo_fails() {
+        let url = &server_url();
+        let _timeline = mock("GET", "/thumbnails/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body(timeline(
+                "105",
+                "<p>Nāc un piedalies arī Tu!</p>",
+                r#"<a rel="theater" href="/kantineKliversala/photos/a.1/202/"><img src="https://example.com/p526x296/202_n.jpg"></a>"#,
+            ))
+            .create();
+        let _photo = mock("GET", "/kantineKliversala/photos/a.1/202/")
+            .with_status(404)
+            .with_body("error")
+            .create();
+
+        let result = fetch_posts(&format!("{}/thumbnails/kantineKliversala/posts/", url), &[])
+            .await
+            .unwrap();
+        assert_eq!(
+            result[0].images,
+            vec![Image::new("https://example.com/p526x296/202_n.jpg")]
+        );
+        _photo.assert();
+    }
+
+    // A post as stored by an earlier run.
+    fn known_post(id: &str, text: &str, images: Vec<Image>) -> Post {
+        Post {
+            tg_id: Some(String::from("10")),
+            published: Some(1581059445),
+            source: String::from(NAME),
+            ..Post::new(id, text, images)
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_keeps_full_text_of_known_post() {
+        let url = &server_url();
+        let _timeline = mock("GET", "/known/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body(timeline(
+                "106",
+                r#"<p>Pusdienu piedāvājums 7. februārī. <a href="/kantineKliversala/posts/106">See more</a></p>"#,
+                r#"<a rel="theater" href="/kantineKliversala/photos/a.1/206/"><img src="https://example.com/p526x296/206_n.jpg"></a>"#,
+            ))
+            .create();
+        let _post = mock("GET", "/kantineKliversala/posts/106")
+            .expect(0)
+            .create();
+        let _photo = mock("GET", "/kantineKliversala/photos/a.1/206/")
+            .expect(0)
+            .create();
+
+        let images = vec![
+            Image::full_size(
+                "https://example.com/206_o.jpg",
+                "https://example.com/p526x296/206_n.jpg",
+            ),
+            Image::full_size(
+                "https://example.com/207_o.jpg",
+                "https://example.com/207_n.jpg",
+            ),
+        ];
+        let known = known_post(
+            "106",
+            "Pusdienu piedāvājums 7. februārī.\n\nLielais pusdienu piedāvājums: frikadeļu zupa",
+            images.clone(),
+        );
+        let result = fetch_posts(&format!("{}/known/kantineKliversala/posts/", url), &[known])
+            .await
+            .unwrap();
+        assert_eq!(
+            result[0].text,
+            "Pusdienu piedāvājums 7. februārī.\n\nLielais pusdienu piedāvājums: frikadeļu zupa"
+        );
+        assert_eq!(result[0].images, images);
+        assert_eq!(result[0].tg_id, None);
+        assert!(!result[0].truncated);
+        _post.assert();
+        _photo.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_reads_recent_known_post_again() {
+        let url = &server_url();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let published = (now - Duration::from_secs(60 * 60)).as_secs();
+        let _timeline = mock("GET", "/recent/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body(
+                timeline(
+                    "109",
+                    r#"<p>Pusdienu piedāvājums 7. februārī. <a href="/kantineKliversala/posts/109">See more</a></p>"#,
+                    "",
+                )
+                .replace("1581059445", &published.to_string()),
+            )
+            .create();
+        let _post = mock("GET", "/kantineKliversala/posts/109")
+            .with_status(200)
+            .with_body(FULL_POST)
+            .create();
+
+        // The timeline shows the same beginning, the change is in the hidden part of the text.
+        let known = Post {
+            published: Some(published),
+            ..known_post(
+                "109",
+                "Pusdienu piedāvājums 7. februārī.\n\nLielais pusdienu piedāvājums: skābeņu zupa",
+                vec![],
+            )
+        };
+        let result = fetch_posts(
+            &format!("{}/recent/kantineKliversala/posts/", url),
+            &[known],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            result[0].text,
+            "Pusdienu piedāvājums 7. februārī.\n\nLielais pusdienu piedāvājums: frikadeļu zupa"
+        );
+        _post.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_reads_changed_known_post_again() {
+        let url = &server_url();
+        let _timeline = mock("GET", "/changed/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body(timeline(
+                "107",
+                r#"<p>Pusdienu piedāvājums 7. februārī. <a href="/kantineKliversala/posts/107">See more</a></p>"#,
+                "",
+            ))
+            .create();
+        let _post = mock("GET", "/kantineKliversala/posts/107")
+            .with_status(200)
+            .with_body(FULL_POST)
+            .create();
+
+        let known = known_post(
+            "107",
+            "Pusdienu piedāvājums 6. februārī.\n\nLielais pusdienu piedāvājums: skābeņu zupa",
+            vec![],
+        );
+        let result = fetch_posts(
+            &format!("{}/changed/kantineKliversala/posts/", url),
+            &[known],
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            result[0].text,
+            "Pusdienu piedāvājums 7. februārī.\n\nLielais pusdienu piedāvājums: frikadeļu zupa"
+        );
+        _post.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_keeps_full_size_of_known_images() {
+        let url = &server_url();
+        let _timeline = mock("GET", "/sized/kantineKliversala/posts/")
+            .with_status(200)
+            .with_body(timeline(
+                "108",
+                "<p>Nāc un piedalies arī Tu!</p>",
+                r#"<a rel="theater" href="/kantineKliversala/photos/a.1/208/"><img src="https://example.com/p526x296/208_n.jpg?oe=2"></a>
+                <a rel="theater" href="/kantineKliversala/photos/a.1/209/"><img src="https://example.com/p526x296/209_n.jpg"></a>"#,
+            ))
+            .create();
+        let _known_photo = mock("GET", "/kantineKliversala/photos/a.1/208/")
+            .expect(0)
+            .create();
+        let _new_photo = mock("GET", "/kantineKliversala/photos/a.1/209/")
+            .with_status(200)
+            .with_body(r#"<html><head><meta property="og:image" content="https://example.com/209_o.jpg"></head></html>"#)
+            .create();
+
+        let known_image = Image::full_size(
+            "https://example.com/208_o.jpg",
+            "https://example.com/p526x296/208_n.jpg?oe=1",
+        );
+        let known = known_post("108", "Nāc un piedalies arī Tu!", vec![known_image.clone()]);
+        let result = fetch_posts(&format!("{}/sized/kantineKliversala/posts/", url), &[known])
+            .await
+            .unwrap();
+        assert_eq!(
+            result[0].images,
+            vec![
+                known_image,
+                Image::full_size(
+                    "https://example.com/209_o.jpg",
+                    "https://example.com/p526x296/209_n.jpg"
+                )
+            ]
+        );
+        _known_photo.assert();
+        _new_photo.assert();
+    }
+
+    #[tokio::test]
+    async fn is_deleted_when_post_page_is_gone() {
+        let _gone = mock("GET", "/kantineKliversala/posts/301")
+            .with_status(404)
+            .create();
+        let _kept = mock("GET", "/kantineKliversala/posts/302")
+            .with_status(200)
+            .with_body(FULL_POST)
+            .create();
+
+        let source = FacebookSource::new(&server_url());
+        let post = |id: &str| Post {
+            permalink: Some(format!("{}/kantineKliversala/posts/{}", server_url(), id)),
+            ..Post::new(&format!("facebook:{}", id), "", vec![])
+        };
+        assert!(source.is_deleted(&post("301")).await.unwrap());
+        assert!(!source.is_deleted(&post("302")).await.unwrap());
+        let without_permalink = Post {
+            permalink: None,
+            ..post("303")
+        };
+        assert!(!source.is_deleted(&without_permalink).await.unwrap());
+        _gone.assert();
+        _kept.assert();
+    }
+
     #[test]
     fn remove_markdown_links_single_works() {
         let test_string = r#"test [Skatīt vairāk](/kantineKliversala/posts/2457708144491355)"#;
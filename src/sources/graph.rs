@@ -0,0 +1,406 @@
+use log::info;
+use reqwest::{Client, RequestBuilder, Url};
+use serde::Deserialize;
+
+use async_trait::async_trait;
+
+use crate::error::Error;
+use crate::sources::{Image, Post, PostSource};
+
+const FIELDS: &str = "message,created_time,permalink_url,from,attachments{media,subattachments}";
+
+// The Graph API reads the same page as `FacebookSource` and the posts keep their ids, so that
+// switching between the two does not send the posts again.
+const NAME: &str = "facebook";
+
+/// Reads the posts of a Facebook page through the Graph API, which needs a page access token but
+/// does not break when Facebook changes the markup of its pages.
+pub struct GraphSource {
+    page_id: String,
+    access_token: String,
+    domain: String,
+    max_posts: usize,
+}
+
+impl GraphSource {
+    pub fn new(page_id: String, access_token: String) -> GraphSource {
+        GraphSource::new_with(
+            page_id,
+            access_token,
+            String::from("https://graph.facebook.com/v6.0"),
+        )
+    }
+
+    pub fn new_with(page_id: String, access_token: String, domain: String) -> GraphSource {
+        GraphSource {
+            page_id,
+            access_token,
+            domain,
+            max_posts: 25,
+        }
+    }
+
+    /// Limits how many of the most recent posts are read, following as many pages as needed.
+    pub fn with_max_posts(mut self, max_posts: usize) -> GraphSource {
+        self.max_posts = max_posts;
+        self
+    }
+}
+
+#[async_trait]
+impl PostSource for GraphSource {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    async fn fetch_posts(&self, _known: &[Post]) -> Result<Vec<Post>, Error> {
+        let client = Client::new();
+        let limit = self.max_posts.to_string();
+        let mut request = client
+            .get(&format!("{}/{}/posts", self.domain, self.page_id))
+            .query(&[("fields", FIELDS), ("limit", &limit), ("date_format", "U")])
+            .bearer_auth(&self.access_token);
+
+        let mut result = Vec::new();
+        loop {
+            let page = fetch_page(request).await?;
+            result.extend(page.data.into_iter().map(build_post));
+            match page.paging.and_then(|paging| paging.next) {
+                Some(next) if result.len() < self.max_posts => {
+                    info!("fetching next page of posts");
+                    request = client
+                        .get(without_token(&next)?)
+                        .bearer_auth(&self.access_token);
+                }
+                _ => break,
+            }
+        }
+        result.truncate(self.max_posts);
+        Ok(result)
+    }
+}
+
+#[derive(Deserialize)]
+struct Page {
+    data: Vec<GraphPost>,
+    paging: Option<Paging>,
+}
+
+#[derive(Deserialize)]
+struct Paging {
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GraphPost {
+    id: String,
+    message: Option<String>,
+    created_time: Option<u64>,
+    permalink_url: Option<String>,
+    from: Option<Author>,
+    attachments: Option<Attachments>,
+}
+
+#[derive(Deserialize)]
+struct Author {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Attachments {
+    data: Vec<Attachment>,
+}
+
+#[derive(Deserialize)]
+struct Attachment {
+    media: Option<Media>,
+    subattachments: Option<Attachments>,
+}
+
+#[derive(Deserialize)]
+struct Media {
+    image: MediaImage,
+}
+
+#[derive(Deserialize)]
+struct MediaImage {
+    src: String,
+}
+
+async fn fetch_page(request: RequestBuilder) -> Result<Page, Error> {
+    let resp = request.send().await?;
+
+    if !resp.status().is_success() {
+        return Err(Error::SourceStatus {
+            status: resp.status().as_u16(),
+            body: resp.text().await?,
+        });
+    }
+
+    let body = resp.text().await?;
+    serde_json::from_str(&body)
+        .map_err(|error| Error::SourceParse(format!("unexpected graph api response: {}", error)))
+}
+
+// The link to the next page carries the query of the first one, token included, which would end
+// up in logged errors, so the token is sent only in the header.
+fn without_token(link: &str) -> Result<Url, Error> {
+    let mut url = Url::parse(link)
+        .map_err(|error| Error::SourceParse(format!("invalid next page link: {}", error)))?;
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(name, _)| name != "access_token")
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut().clear().extend_pairs(query);
+    Ok(url)
+}
+
+fn build_post(post: GraphPost) -> Post {
+    let images = post
+        .attachments
+        .map(|attachments| images(attachments.data))
+        .unwrap_or_default();
+    Post {
+        published: post.created_time,
+        source: String::from(NAME),
+        permalink: post.permalink_url,
+        author: post.from.map(|from| from.name),
+        ..Post::new(
+            &post_id(&post.id),
+            post.message.as_deref().unwrap_or_default(),
+            images,
+        )
+    }
+}
+
+// Albums list their photos as subattachments, with the cover as the media of the album itself.
+fn images(attachments: Vec<Attachment>) -> Vec<Image> {
+    attachments
+        .into_iter()
+        .flat_map(|attachment| match attachment.subattachments {
+            Some(subattachments) => images(subattachments.data),
+            None => attachment
+                .media
+                .map(|media| Image::new(&media.image.src))
+                .into_iter()
+                .collect(),
+        })
+        .collect()
+}
+
+// Graph API ids are prefixed with the id of the page, the timeline only shows the rest.
+fn post_id(id: &str) -> String {
+    match id.find('_') {
+        Some(end) => String::from(&id[end + 1..]),
+        None => String::from(id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{mock, server_url, Matcher};
+
+    use super::*;
+
+    fn source(page_id: &str) -> GraphSource {
+        GraphSource::new_with(String::from(page_id), String::from("token"), server_url())
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_success() {
+        let _m = mock("GET", "/graphPage/posts")
+            .match_header("authorization", "Bearer token")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("fields".into(), FIELDS.into()),
+                Matcher::UrlEncoded("date_format".into(), "U".into()),
+                Matcher::UrlEncoded("limit".into(), "25".into()),
+            ]))
+            .with_status(200)
+            .with_body(
+                r#"{
+                    "data": [
+                        {
+                            "id": "1609930535935791_2471140943148075",
+                            "message": "Pusdienu piedāvājums 7. februārī.\nDienas piedāvājums pieejams 11:00-16:00",
+                            "created_time": 1581059445,
+                            "permalink_url": "https://www.facebook.com/kantineKliversala/posts/2471140943148075",
+                            "from": {"name": "Kantīne \"Klīversala\"", "id": "1609930535935791"}
+                        },
+                        {
+                            "id": "1609930535935791_2465890140339822",
+                            "message": "Nāc un piedalies arī Tu, jau no 01.02.2020! 🥘🍴☕",
+                            "created_time": 1580465130,
+                            "attachments": {"data": [{
+                                "media": {"image": {"height": 720, "src": "https://scontent.xx.fbcdn.net/v/1_o.jpg?oe=1", "width": 480}},
+                                "type": "photo"
+                            }]}
+                        },
+                        {
+                            "id": "1609930535935791_2460218670906969",
+                            "created_time": 1579073415,
+                            "attachments": {"data": [{
+                                "media": {"image": {"src": "https://scontent.xx.fbcdn.net/v/2_o.jpg"}},
+                                "subattachments": {"data": [
+                                    {"media": {"image": {"src": "https://scontent.xx.fbcdn.net/v/2_o.jpg"}}},
+                                    {"media": {"image": {"src": "https://scontent.xx.fbcdn.net/v/3_o.jpg"}}}
+                                ]},
+                                "type": "album"
+                            }]}
+                        }
+                    ],
+                    "paging": {"cursors": {"before": "a", "after": "b"}}
+                }"#,
+            )
+            .create();
+
+        let result = source("graphPage").fetch_posts(&[]).await.unwrap();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].id, "2471140943148075");
+        assert_eq!(
+            result[0].text,
+            "Pusdienu piedāvājums 7. februārī.\nDienas piedāvājums pieejams 11:00-16:00"
+        );
+        assert_eq!(result[0].published, Some(1581059445));
+        assert_eq!(
+            result[0].permalink,
+            Some(String::from(
+                "https://www.facebook.com/kantineKliversala/posts/2471140943148075"
+            ))
+        );
+        assert_eq!(
+            result[0].author,
+            Some(String::from("Kantīne \"Klīversala\""))
+        );
+        assert_eq!(result[0].source, "facebook");
+        assert_eq!(result[0].images, vec![]);
+
+        assert_eq!(
+            result[1].images,
+            vec![Image::new("https://scontent.xx.fbcdn.net/v/1_o.jpg?oe=1")]
+        );
+        assert_eq!(result[2].text, "");
+        assert_eq!(
+            result[2].images,
+            vec![
+                Image::new("https://scontent.xx.fbcdn.net/v/2_o.jpg"),
+                Image::new("https://scontent.xx.fbcdn.net/v/3_o.jpg")
+            ]
+        );
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_follows_paging() {
+        let next = format!(
+            "{}/v6.0/pagedPage/posts?access_token=token&after=cursor",
+            server_url()
+        );
+        let _first = mock("GET", "/pagedPage/posts")
+            .match_header("authorization", "Bearer token")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body(format!(
+                r#"{{"data": [{{"id": "1_100", "message": "first"}}], "paging": {{"next": "{}"}}}}"#,
+                next
+            ))
+            .create();
+        let _second = mock("GET", "/v6.0/pagedPage/posts")
+            .match_header("authorization", "Bearer token")
+            .match_query(Matcher::Regex("^after=cursor$".into()))
+            .with_status(200)
+            .with_body(r#"{"data": [{"id": "1_99", "message": "second"}], "paging": {}}"#)
+            .create();
+
+        let source = source("pagedPage");
+        let result = source.fetch_posts(&[]).await.unwrap();
+        assert_eq!(
+            result
+                .iter()
+                .map(|post| post.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["100", "99"]
+        );
+        _first.assert();
+        _second.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_stops_at_max_posts() {
+        let next = format!("{}/v6.0/limitedPage/posts?after=cursor", server_url());
+        let _first = mock("GET", "/limitedPage/posts")
+            .match_query(Matcher::UrlEncoded("limit".into(), "2".into()))
+            .with_status(200)
+            .with_body(format!(
+                r#"{{"data": [{{"id": "1_100"}}, {{"id": "1_99"}}], "paging": {{"next": "{}"}}}}"#,
+                next
+            ))
+            .create();
+        let _second = mock("GET", "/v6.0/limitedPage/posts")
+            .match_query(Matcher::Any)
+            .expect(0)
+            .create();
+
+        let source = source("limitedPage").with_max_posts(2);
+        let result = source.fetch_posts(&[]).await.unwrap();
+        assert_eq!(result.len(), 2);
+        _first.assert();
+        _second.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_error() {
+        let _m = mock("GET", "/errorPage/posts")
+            .match_query(Matcher::Any)
+            .with_status(400)
+            .with_body(r#"{"error": {"message": "Invalid OAuth access token.", "code": 190}}"#)
+            .create();
+
+        let source = source("errorPage");
+        match source.fetch_posts(&[]).await.unwrap_err() {
+            Error::SourceStatus { status, body } => {
+                assert_eq!(status, 400);
+                assert!(body.contains("Invalid OAuth access token."));
+            }
+            error => panic!("unexpected error: {:?}", error),
+        }
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn fetch_posts_unexpected_response() {
+        let _m = mock("GET", "/brokenPage/posts")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_body("<html></html>")
+            .create();
+
+        let source = source("brokenPage");
+        match source.fetch_posts(&[]).await.unwrap_err() {
+            Error::SourceParse(_) => {}
+            error => panic!("unexpected error: {:?}", error),
+        }
+        _m.assert();
+    }
+
+    #[test]
+    fn next_page_link_without_token() {
+        assert_eq!(
+            without_token(
+                "https://graph.facebook.com/v6.0/1/posts?access_token=secret&limit=25&after=b"
+            )
+            .unwrap()
+            .as_str(),
+            "https://graph.facebook.com/v6.0/1/posts?limit=25&after=b"
+        );
+    }
+
+    #[test]
+    fn post_id_without_page_id() {
+        assert_eq!(
+            post_id("1609930535935791_2471140943148075"),
+            "2471140943148075"
+        );
+        assert_eq!(post_id("2471140943148075"), "2471140943148075");
+    }
+}
@@ -1,27 +1,232 @@
-use std::error::Error;
-
 use async_trait::async_trait;
 
+use crate::error::Error;
+
+pub mod classifier;
 pub mod facebook;
+pub mod graph;
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Post {
     pub id: String,
     pub tg_id: Option<String>,
+    /// Messages following `tg_id` with the rest of a text too long for a single message.
+    pub continuation_ids: Vec<String>,
     pub text: String,
     pub images: Vec<Image>,
+    pub status: PostStatus,
+    pub kind: PostKind,
+    /// When the post was published, in seconds since the Unix epoch, if the source tells.
+    pub published: Option<u64>,
+    /// Name of the source the post was fetched from, see `PostSource::name`.
+    pub source: String,
+    /// Link to the original post.
+    pub permalink: Option<String>,
+    /// Name of the page or person that published the post.
+    pub author: Option<String>,
+    /// The source could only read the beginning of the text, so the post is neither sent nor
+    /// edited until a later run reads all of it. Never stored.
+    pub truncated: bool,
+}
+
+impl Post {
+    /// A post as read from a source, with its kind told from the text and none of the details the
+    /// source may add.
+    pub fn new(id: &str, text: &str, images: Vec<Image>) -> Post {
+        Post {
+            id: String::from(id),
+            tg_id: None,
+            continuation_ids: vec![],
+            text: String::from(text),
+            images,
+            status: PostStatus::Sent,
+            kind: classifier::classify(text),
+            published: None,
+            source: String::new(),
+            permalink: None,
+            author: None,
+            truncated: false,
+        }
+    }
+}
+
+/// How far sending a stored post has got. Posts fetched from a source are always `Sent`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PostStatus {
+    /// Sending has started, only the messages with an id are known to have reached the channel.
+    Sending,
+    /// Every message of the post has been sent.
+    Sent,
+    /// The post has been recorded without sending it, as it was too old or seeded.
+    Skipped,
+}
+
+impl PostStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PostStatus::Sending => "sending",
+            PostStatus::Sent => "sent",
+            PostStatus::Skipped => "skipped",
+        }
+    }
+
+    /// Parses a stored status, treating records written before statuses were kept as sent.
+    pub fn parse(status: Option<&str>) -> PostStatus {
+        match status {
+            Some("sending") => PostStatus::Sending,
+            Some("skipped") => PostStatus::Skipped,
+            _ => PostStatus::Sent,
+        }
+    }
+}
+
+/// What a post is about, which decides the chat it is forwarded to, see `classifier::classify`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PostKind {
+    DailyMenu,
+    WeeklyMenu,
+    Announcement,
+    Promotion,
+}
+
+impl PostKind {
+    pub const ALL: [PostKind; 4] = [
+        PostKind::DailyMenu,
+        PostKind::WeeklyMenu,
+        PostKind::Announcement,
+        PostKind::Promotion,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PostKind::DailyMenu => "daily_menu",
+            PostKind::WeeklyMenu => "weekly_menu",
+            PostKind::Announcement => "announcement",
+            PostKind::Promotion => "promotion",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<PostKind> {
+        PostKind::ALL
+            .iter()
+            .copied()
+            .find(|known| known.as_str() == kind)
+    }
+
+    /// Parses a stored kind, classifying records written before kinds were kept by their text.
+    pub fn parse_stored(kind: Option<&str>, text: &str) -> PostKind {
+        kind.and_then(PostKind::parse)
+            .unwrap_or_else(|| classifier::classify(text))
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Image {
     pub url: String,
+    /// The resized picture the source shows in place of `url`, if `url` is its full size.
+    pub thumbnail_url: Option<String>,
+    /// Identifies the picture behind `url`, see `image_key`.
+    pub key: String,
     pub tg_id: Option<String>,
 }
 
+impl Image {
+    pub fn new(url: &str) -> Image {
+        Image {
+            url: String::from(url),
+            thumbnail_url: None,
+            key: image_key(url),
+            tg_id: None,
+        }
+    }
+
+    pub fn full_size(url: &str, thumbnail_url: &str) -> Image {
+        Image {
+            thumbnail_url: Some(String::from(thumbnail_url)),
+            ..Image::new(url)
+        }
+    }
+}
+
+/// Identifies the picture behind an image URL.
+///
+/// Facebook serves the same photo from different CDN hosts and re-signs its URLs with rotating
+/// query parameters (`_nc_ohc`, `oh`, `oe`), while the file name stays the same.
+pub fn image_key(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    if file_name.is_empty() {
+        String::from(path)
+    } else {
+        String::from(file_name)
+    }
+}
+
+#[async_trait]
+pub trait PostSource: Send + Sync {
+    /// Names the source in logs and in the keys its posts are stored under, so it must not
+    /// change between runs. Only sources reading the same posts may share a name.
+    fn name(&self) -> &str;
+    /// Reads the most recent posts. `known` are the posts of this source that are already stored,
+    /// under the ids the source gave them, so that what has not changed need not be read again.
+    async fn fetch_posts(&self, known: &[Post]) -> Result<Vec<Post>, Error>;
+
+    /// Whether a stored post that is missing from `fetch_posts` has really been deleted, rather
+    /// than skipped. Sources whose listing never skips posts need not check.
+    async fn is_deleted(&self, _post: &Post) -> Result<bool, Error> {
+        Ok(true)
+    }
+}
+
 #[async_trait]
-pub trait PostSource {
-    type Source;
+impl<T: PostSource + ?Sized> PostSource for Box<T> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    async fn fetch_posts(&self, known: &[Post]) -> Result<Vec<Post>, Error> {
+        (**self).fetch_posts(known).await
+    }
+
+    async fn is_deleted(&self, post: &Post) -> Result<bool, Error> {
+        (**self).is_deleted(post).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_key_ignores_signature_and_host() {
+        let first = "https://scontent.frix2-1.fna.fbcdn.net/v/t1.0-9/p526x296/84358794_2465890143473155_6563431094404317184_n.jpg?_nc_cat=104&_nc_ohc=AQmqyzyJ&_nc_ht=scontent.frix2-1.fna&oh=3d1c&oe=5EC4F3E0";
+        let second = "https://scontent.frix7-1.fna.fbcdn.net/v/t1.0-9/p526x296/84358794_2465890143473155_6563431094404317184_n.jpg?_nc_cat=104&_nc_ohc=B1xzV0Kq&_nc_ht=scontent.frix7-1.fna&oh=9a2b&oe=5ED8A1C4";
+        assert_eq!(image_key(first), image_key(second));
+        assert_eq!(
+            image_key(first),
+            "84358794_2465890143473155_6563431094404317184_n.jpg"
+        );
+    }
+
+    #[test]
+    fn image_key_tells_pictures_apart() {
+        assert_ne!(
+            image_key("https://scontent.xx.fbcdn.net/v/t1.0-9/1_n.jpg?oe=1"),
+            image_key("https://scontent.xx.fbcdn.net/v/t1.0-9/2_n.jpg?oe=1")
+        );
+    }
+
+    #[test]
+    fn post_kind_roundtrip() {
+        for kind in &PostKind::ALL {
+            assert_eq!(PostKind::parse(kind.as_str()), Some(*kind));
+        }
+        assert_eq!(PostKind::parse("menu"), None);
+    }
 
-    fn new(url: &str) -> Self::Source;
-    async fn fetch_posts(&self) -> Result<Vec<Post>, Box<dyn Error>>;
+    #[test]
+    fn image_key_of_url_without_file_name() {
+        assert_eq!(image_key("url-1"), "url-1");
+        assert_eq!(image_key("https://example.com/"), "https://example.com/");
+    }
 }
@@ -0,0 +1,401 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use log::{debug, error, info};
+use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+
+use crate::error::Error;
+use crate::sources::{Image, Post, PostKind, PostStatus};
+use crate::store::PostStore;
+
+// Each entry upgrades the schema by one version, tracked in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[r#"
+    CREATE TABLE posts (
+        id TEXT PRIMARY KEY NOT NULL,
+        text TEXT NOT NULL,
+        message_id TEXT,
+        status TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        published INTEGER,
+        source TEXT NOT NULL,
+        permalink TEXT,
+        author TEXT
+    );
+    CREATE TABLE images (
+        post_id TEXT NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        url TEXT NOT NULL,
+        thumbnail_url TEXT,
+        key TEXT NOT NULL,
+        message_id TEXT,
+        PRIMARY KEY (post_id, position)
+    );
+    CREATE TABLE continuations (
+        post_id TEXT NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        message_id TEXT NOT NULL,
+        PRIMARY KEY (post_id, position)
+    );
+"#];
+
+pub struct SqliteClient {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteClient {
+    pub fn new(path: &str) -> SqliteResult<SqliteClient> {
+        SqliteClient::new_with(Connection::open(path)?)
+    }
+
+    pub fn new_in_memory() -> SqliteResult<SqliteClient> {
+        SqliteClient::new_with(Connection::open_in_memory()?)
+    }
+
+    fn new_with(mut connection: Connection) -> SqliteResult<SqliteClient> {
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        migrate(&mut connection)?;
+        Ok(SqliteClient {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    pub async fn get_post(&self, id: &str) -> SqliteResult<Option<Post>> {
+        let connection = self.connection.lock().unwrap();
+        match select_post(&connection, id) {
+            Ok(Some(post)) => {
+                info!("get_post: Ok(id: {})", id);
+                Ok(Some(post))
+            }
+            Ok(None) => {
+                info!("get_post: post {} not found", id);
+                Ok(None)
+            }
+            Err(error) => {
+                error!("get_post: Error: {:?}", error);
+                Err(error)
+            }
+        }
+    }
+
+    pub async fn put_post(&self, post: &Post) -> SqliteResult<()> {
+        debug!("put_post: {:?}", post);
+        let mut connection = self.connection.lock().unwrap();
+        match insert_post(&mut connection, post) {
+            Ok(()) => {
+                info!("put_post: Ok(id: {})", post.id);
+                Ok(())
+            }
+            Err(error) => {
+                error!("put_post: Error: {:?}", error);
+                Err(error)
+            }
+        }
+    }
+
+    pub async fn scan_posts(&self, published_since: u64) -> SqliteResult<Vec<Post>> {
+        let connection = self.connection.lock().unwrap();
+        let result = connection
+            .prepare("SELECT id FROM posts WHERE published IS NULL OR published >= ?1 ORDER BY id")
+            .and_then(|mut statement| {
+                statement
+                    .query_map(params![published_since as i64], |row| {
+                        row.get::<_, String>(0)
+                    })?
+                    .collect::<SqliteResult<Vec<String>>>()
+            })
+            .and_then(|ids| {
+                ids.iter()
+                    .filter_map(|id| select_post(&connection, id).transpose())
+                    .collect::<SqliteResult<Vec<Post>>>()
+            });
+        match result {
+            Ok(posts) => {
+                info!("scan: Ok(count: {})", posts.len());
+                Ok(posts)
+            }
+            Err(error) => {
+                error!("scan: Error: {:?}", error);
+                Err(error)
+            }
+        }
+    }
+
+    pub async fn delete_post(&self, id: &str) -> SqliteResult<()> {
+        let connection = self.connection.lock().unwrap();
+        match connection.execute("DELETE FROM posts WHERE id = ?1", params![id]) {
+            Ok(_) => {
+                info!("delete_post: Ok(id: {})", id);
+                Ok(())
+            }
+            Err(error) => {
+                error!("delete_post: Error: {:?}", error);
+                Err(error)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl PostStore for SqliteClient {
+    async fn get_post(&self, id: &str) -> Result<Option<Post>, Error> {
+        Ok(SqliteClient::get_post(self, id).await?)
+    }
+
+    async fn put_post(&self, post: &Post) -> Result<(), Error> {
+        Ok(SqliteClient::put_post(self, post).await?)
+    }
+
+    async fn scan_posts(&self, published_since: u64) -> Result<Vec<Post>, Error> {
+        Ok(SqliteClient::scan_posts(self, published_since).await?)
+    }
+
+    async fn delete_post(&self, id: &str) -> Result<(), Error> {
+        Ok(SqliteClient::delete_post(self, id).await?)
+    }
+}
+
+fn migrate(connection: &mut Connection) -> SqliteResult<()> {
+    let version: usize =
+        connection.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))? as usize;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        info!("migrate: applying schema version {}", index + 1);
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.execute_batch(&format!("PRAGMA user_version = {}", index + 1))?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+fn select_post(connection: &Connection, id: &str) -> SqliteResult<Option<Post>> {
+    let post = connection
+        .query_row(
+            "SELECT text, message_id, status, kind, published, source, permalink, author
+             FROM posts WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    let (text, tg_id, status, kind, published, source, permalink, author) = match post {
+        Some(post) => post,
+        None => return Ok(None),
+    };
+
+    let mut statement = connection
+        .prepare(
+        "SELECT url, message_id, key, thumbnail_url FROM images WHERE post_id = ?1 ORDER BY position",
+    )?;
+    let images = statement
+        .query_map(params![id], |row| {
+            Ok(Image {
+                url: row.get(0)?,
+                thumbnail_url: row.get(3)?,
+                key: row.get(2)?,
+                tg_id: row.get(1)?,
+            })
+        })?
+        .collect::<SqliteResult<Vec<Image>>>()?;
+
+    let mut statement = connection
+        .prepare("SELECT message_id FROM continuations WHERE post_id = ?1 ORDER BY position")?;
+    let continuation_ids = statement
+        .query_map(params![id], |row| row.get(0))?
+        .collect::<SqliteResult<Vec<String>>>()?;
+
+    Ok(Some(Post {
+        id: String::from(id),
+        tg_id,
+        continuation_ids,
+        kind: PostKind::parse_stored(Some(&kind), &text),
+        published: published.map(|published| published as u64),
+        source,
+        permalink,
+        author,
+        truncated: false,
+        text,
+        images,
+        status: PostStatus::parse(Some(&status)),
+    }))
+}
+
+fn insert_post(connection: &mut Connection, post: &Post) -> SqliteResult<()> {
+    let transaction = connection.transaction()?;
+    transaction.execute(
+        "INSERT INTO posts (id, text, message_id, status, kind, published, source, permalink, author)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+         ON CONFLICT (id) DO UPDATE SET
+            text = excluded.text, message_id = excluded.message_id, status = excluded.status,
+            kind = excluded.kind, published = excluded.published, source = excluded.source,
+            permalink = excluded.permalink, author = excluded.author",
+        params![
+            post.id,
+            post.text,
+            post.tg_id,
+            post.status.as_str(),
+            post.kind.as_str(),
+            post.published.map(|published| published as i64),
+            post.source,
+            post.permalink,
+            post.author
+        ],
+    )?;
+    transaction.execute("DELETE FROM images WHERE post_id = ?1", params![post.id])?;
+    for (position, image) in post.images.iter().enumerate() {
+        transaction.execute(
+            "INSERT INTO images (post_id, position, url, message_id, key, thumbnail_url)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                post.id,
+                position as i64,
+                image.url,
+                image.tg_id,
+                image.key,
+                image.thumbnail_url
+            ],
+        )?;
+    }
+    transaction.execute(
+        "DELETE FROM continuations WHERE post_id = ?1",
+        params![post.id],
+    )?;
+    for (position, message_id) in post.continuation_ids.iter().enumerate() {
+        transaction.execute(
+            "INSERT INTO continuations (post_id, position, message_id) VALUES (?1, ?2, ?3)",
+            params![post.id, position as i64, message_id],
+        )?;
+    }
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post() -> Post {
+        let images = vec![
+            Image {
+                tg_id: Some(String::from("11")),
+                ..Image::new("url-1")
+            },
+            Image {
+                tg_id: None,
+                ..Image::full_size("url-2", "thumbnail-2")
+            },
+        ];
+        Post {
+            tg_id: Some(String::from("10")),
+            continuation_ids: vec![String::from("12")],
+            published: Some(1581059445),
+            source: String::from("facebook"),
+            permalink: Some(String::from(
+                "https://www.facebook.com/kantineKliversala/posts/1",
+            )),
+            author: Some(String::from("Kantīne \"Klīversala\"")),
+            ..Post::new("1", "text", images)
+        }
+    }
+
+    #[tokio::test]
+    async fn put_and_get_post() {
+        let client = SqliteClient::new_in_memory().unwrap();
+        client.put_post(&post()).await.unwrap();
+
+        let result = client.get_post("1").await.unwrap().unwrap();
+        assert_eq!(result, post());
+    }
+
+    #[tokio::test]
+    async fn get_missing_post() {
+        let client = SqliteClient::new_in_memory().unwrap();
+        assert_eq!(client.get_post("1").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn put_post_replaces_images() {
+        let client = SqliteClient::new_in_memory().unwrap();
+        client.put_post(&post()).await.unwrap();
+
+        let mut updated = post();
+        updated.images.remove(0);
+        client.put_post(&updated).await.unwrap();
+
+        let result = client.get_post("1").await.unwrap().unwrap();
+        assert_eq!(result, updated);
+    }
+
+    #[tokio::test]
+    async fn scan_leaves_out_older_posts() {
+        let client = SqliteClient::new_in_memory().unwrap();
+        client.put_post(&post()).await.unwrap();
+        let undated = Post {
+            id: String::from("2"),
+            published: None,
+            ..post()
+        };
+        client.put_post(&undated).await.unwrap();
+
+        assert_eq!(client.scan_posts(1581059446).await.unwrap(), vec![undated]);
+        assert_eq!(client.scan_posts(1581059445).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn scan_and_delete_posts() {
+        let client = SqliteClient::new_in_memory().unwrap();
+        client.put_post(&post()).await.unwrap();
+        assert_eq!(client.scan_posts(0).await.unwrap(), vec![post()]);
+
+        client.delete_post("1").await.unwrap();
+        assert_eq!(client.scan_posts(0).await.unwrap(), vec![]);
+
+        let connection = client.connection.lock().unwrap();
+        let images: i64 = connection
+            .query_row("SELECT COUNT(*) FROM images", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(images, 0);
+        let continuations: i64 = connection
+            .query_row("SELECT COUNT(*) FROM continuations", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(continuations, 0);
+    }
+
+    #[tokio::test]
+    async fn put_post_updates_status() {
+        let client = SqliteClient::new_in_memory().unwrap();
+        let mut post = post();
+        post.status = PostStatus::Sending;
+        client.put_post(&post).await.unwrap();
+        assert_eq!(client.get_post("1").await.unwrap().unwrap(), post);
+
+        post.status = PostStatus::Sent;
+        client.put_post(&post).await.unwrap();
+        assert_eq!(client.get_post("1").await.unwrap().unwrap(), post);
+
+        post.status = PostStatus::Skipped;
+        client.put_post(&post).await.unwrap();
+        assert_eq!(client.get_post("1").await.unwrap().unwrap(), post);
+    }
+
+    #[test]
+    fn migrate_is_idempotent() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        migrate(&mut connection).unwrap();
+
+        let version: i64 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version as usize, MIGRATIONS.len());
+    }
+}
@@ -0,0 +1,14 @@
+use async_trait::async_trait;
+
+use crate::error::Error;
+use crate::sources::Post;
+
+#[async_trait]
+pub trait PostStore {
+    async fn get_post(&self, id: &str) -> Result<Option<Post>, Error>;
+    async fn put_post(&self, post: &Post) -> Result<(), Error>;
+    /// Posts published before `published_since`, in seconds since the epoch, are left out.
+    /// Posts without a timestamp are always returned.
+    async fn scan_posts(&self, published_since: u64) -> Result<Vec<Post>, Error>;
+    async fn delete_post(&self, id: &str) -> Result<(), Error>;
+}
@@ -1,15 +1,31 @@
-use std::error::Error;
-
-use reqwest::{Client, Response};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::warn;
+use rand::Rng;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, RequestBuilder};
 use serde::Serialize;
-use serde_json::{from_str, Value};
+use serde_json::{from_str, to_string, Value};
+use tokio::time::delay_for;
 
+use super::config::ClientConfig;
 use super::error::TelegramError;
+use super::rate_limiter::RateLimiter;
+use crate::error::Error;
+use crate::formatter::render;
+use crate::publisher::Publisher;
+
+// Content types Telegram accepts for photos.
+const IMAGE_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
 
 #[derive(Serialize)]
 struct Message<'a> {
     chat_id: &'a str,
     text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_mode: Option<&'a str>,
     disable_notification: bool,
 }
 
@@ -20,140 +36,455 @@ struct Image<'a> {
     disable_notification: bool,
 }
 
+#[derive(Serialize)]
+struct InputMediaPhoto<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    media: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    caption: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parse_mode: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct MediaGroup<'a> {
+    chat_id: &'a str,
+    media: Vec<InputMediaPhoto<'a>>,
+    disable_notification: bool,
+}
+
+#[derive(Serialize)]
+struct EditMedia<'a> {
+    chat_id: &'a str,
+    message_id: &'a str,
+    media: InputMediaPhoto<'a>,
+}
+
+// An image downloaded to be uploaded as a file, rather than sent by its URL.
+struct Upload {
+    file_name: String,
+    bytes: Vec<u8>,
+}
+
+impl Upload {
+    fn part(&self) -> Part {
+        Part::bytes(self.bytes.clone()).file_name(self.file_name.clone())
+    }
+}
+
 pub struct TelegramClient {
     token: String,
     chat_id: String,
     domain: String,
+    config: ClientConfig,
+    rate_limiter: RateLimiter,
 }
 
 impl TelegramClient {
     pub fn new(token: String, chat_id: String) -> TelegramClient {
-        TelegramClient {
-            token,
-            chat_id,
-            domain: String::from("https://api.telegram.org"),
-        }
+        TelegramClient::new_with(token, chat_id, String::from("https://api.telegram.org"))
     }
 
     #[allow(dead_code)]
     pub fn new_with(token: String, chat_id: String, domain: String) -> TelegramClient {
+        let config = ClientConfig::default();
         TelegramClient {
             token,
             chat_id,
             domain,
+            rate_limiter: RateLimiter::per_minute(config.messages_per_minute),
+            config,
         }
     }
 
+    pub fn with_config(mut self, config: ClientConfig) -> TelegramClient {
+        self.rate_limiter = RateLimiter::per_minute(config.messages_per_minute);
+        self.config = config;
+        self
+    }
+
+    // Sends the request built by `request`, waiting for the rate limiter before every attempt,
+    // and repeats it while it fails in a way that may go away. Returns the response body.
+    async fn execute<F>(&self, request: F) -> Result<String, TelegramError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        self.execute_with(request, TelegramError::is_retryable)
+            .await
+    }
+
+    // Like `execute`, for requests that send a new message: one that failed after reaching
+    // Telegram may have been posted anyway, so it is only repeated when that cannot be the case.
+    async fn execute_send<F>(&self, request: F) -> Result<String, TelegramError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        self.execute_with(request, TelegramError::is_retryable_send)
+            .await
+    }
+
+    async fn execute_with<F>(
+        &self,
+        request: F,
+        is_retryable: fn(&TelegramError) -> bool,
+    ) -> Result<String, TelegramError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            self.rate_limiter.acquire().await;
+            let error = match send(request()).await {
+                Ok(body) => return Ok(body),
+                Err(error) => error,
+            };
+            let delay = match error {
+                TelegramError::RateLimited { retry_after } => Duration::from_secs(retry_after),
+                ref error if is_retryable(error) => self.backoff(attempt),
+                error => return Err(error),
+            };
+            if attempt >= self.config.max_retries || delay > self.config.max_delay {
+                return Err(error);
+            }
+            attempt += 1;
+            warn!(
+                "Telegram request failed: {}, retrying in {:?} (attempt {} of {})",
+                error, delay, attempt, self.config.max_retries
+            );
+            delay_for(delay).await;
+        }
+    }
+
+    // Exponential backoff with jitter, so that retries of concurrent calls do not line up.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .config
+            .base_delay
+            .checked_mul(2u32.saturating_pow(attempt))
+            .unwrap_or(self.config.max_delay)
+            .min(self.config.max_delay);
+        let jitter = rand::thread_rng().gen_range(0, delay.as_millis() as u64 / 2 + 1);
+        delay / 2 + Duration::from_millis(jitter)
+    }
+
+    fn photo<'a>(&self, media: &'a str, caption: Option<&'a str>) -> InputMediaPhoto<'a> {
+        InputMediaPhoto {
+            kind: "photo",
+            media,
+            caption,
+            parse_mode: caption.and(self.config.parse_mode.as_str()),
+        }
+    }
+
+    // Form of an edit that sets `field` of a message to formatted `value`.
+    fn formatted_form(
+        &self,
+        message_id: &str,
+        field: &'static str,
+        value: &str,
+    ) -> Vec<(&'static str, String)> {
+        let mut form = vec![
+            ("chat_id", self.chat_id.clone()),
+            ("message_id", String::from(message_id)),
+            (field, String::from(value)),
+        ];
+        if let Some(parse_mode) = self.config.parse_mode.as_str() {
+            form.push(("parse_mode", String::from(parse_mode)));
+        }
+        form
+    }
+
     pub async fn send_message(&self, text: &str) -> Result<String, TelegramError> {
         let message = Message {
             chat_id: &self.chat_id,
             text,
+            parse_mode: self.config.parse_mode.as_str(),
             disable_notification: true,
         };
 
         let url = format!("{}/bot{}/sendMessage", self.domain, self.token);
-        let resp: Response = Client::new().post(&url).json(&message).send().await?;
+        let resp = self
+            .execute_send(|| Client::new().post(&url).json(&message))
+            .await?;
+        let resp: Value = from_str(&resp)?;
+        let resp = &resp["result"];
+        let resp = &resp["message_id"];
+        Ok(format!("{}", resp))
+    }
 
-        if resp.status().is_success() {
-            let resp: Value = from_str(&resp.text().await?)?;
-            let resp = &resp["result"];
-            let resp = &resp["message_id"];
-            Ok(format!("{}", resp))
-        } else {
-            Err(resp.text().await?.into())
+    // Downloads an image for uploading, making sure it is one Telegram would accept.
+    async fn download_image(&self, image_url: &str) -> Result<Upload, TelegramError> {
+        let max_size = self.config.max_image_size;
+        let mut resp = Client::new().get(image_url).send().await?;
+        if !resp.status().is_success() {
+            return Err(TelegramError::InvalidImage(format!(
+                "{} responded with {}",
+                image_url,
+                resp.status()
+            )));
+        }
+
+        let content_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_lowercase())
+            .unwrap_or_default();
+        if !IMAGE_TYPES.contains(&content_type.as_str()) {
+            return Err(TelegramError::InvalidImage(format!(
+                "unsupported content type {:?} of {}",
+                content_type, image_url
+            )));
+        }
+
+        let too_large = || {
+            TelegramError::InvalidImage(format!("{} is larger than {} bytes", image_url, max_size))
+        };
+        if resp
+            .content_length()
+            .is_some_and(|length| length > max_size)
+        {
+            return Err(too_large());
         }
+        let mut bytes = vec![];
+        while let Some(chunk) = resp.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() as u64 > max_size {
+                return Err(too_large());
+            }
+        }
+
+        Ok(Upload {
+            file_name: file_name(image_url),
+            bytes,
+        })
     }
 
     pub async fn send_image(&self, image_url: &str) -> Result<String, TelegramError> {
-        let image = Image {
-            chat_id: &self.chat_id,
-            photo: image_url,
-            disable_notification: true,
-        };
         let url = format!("{}/bot{}/sendPhoto", self.domain, self.token);
-        let resp: Response = Client::new().post(&url).json(&image).send().await?;
-
-        if resp.status().is_success() {
-            let resp: Value = from_str(&resp.text().await?)?;
-            let resp = &resp["result"];
-            let resp = &resp["message_id"];
-            Ok(format!("{}", resp))
+        let resp = if self.config.upload_images {
+            let upload = self.download_image(image_url).await?;
+            self.execute_send(|| {
+                let form = Form::new()
+                    .text("chat_id", self.chat_id.clone())
+                    .text("disable_notification", "true")
+                    .part("photo", upload.part());
+                Client::new().post(&url).multipart(form)
+            })
+            .await?
         } else {
-            Err(resp.text().await?.into())
-        }
+            let image = Image {
+                chat_id: &self.chat_id,
+                photo: image_url,
+                disable_notification: true,
+            };
+            self.execute_send(|| Client::new().post(&url).json(&image))
+                .await?
+        };
+        let resp: Value = from_str(&resp)?;
+        let resp = &resp["result"];
+        let resp = &resp["message_id"];
+        Ok(format!("{}", resp))
     }
 
     pub async fn delete_message(&self, message_id: &str) -> Result<(), TelegramError> {
         let url = format!("{}/bot{}/deleteMessage", self.domain, self.token);
-        let resp: Response = Client::new()
-            .post(&url)
-            .form(&[
+        self.execute(|| {
+            Client::new().post(&url).form(&[
                 ("chat_id", &self.chat_id),
                 ("message_id", &String::from(message_id)),
             ])
-            .send()
-            .await?;
-
-        if resp.status().is_success() {
-            Ok(())
-        } else {
-            Err(resp.text().await?.into())
-        }
+        })
+        .await?;
+        Ok(())
     }
 
     pub async fn edit_message_text(
         &self,
         message_id: &str,
         text: &str,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), TelegramError> {
         let url = format!("{}/bot{}/editMessageText", self.domain, self.token);
-        let resp: Response = Client::new()
-            .post(&url)
-            .form(&[
-                ("chat_id", &self.chat_id),
-                ("message_id", &String::from(message_id)),
-                ("text", &String::from(text)),
-            ])
-            .send()
+        let form = self.formatted_form(message_id, "text", text);
+        self.execute(|| Client::new().post(&url).form(&form))
             .await?;
+        Ok(())
+    }
 
-        if resp.status().is_success() {
-            Ok(())
-        } else {
-            Err(resp.text().await?.into())
+    pub async fn send_media_group(
+        &self,
+        image_urls: &[&str],
+        caption: Option<&str>,
+    ) -> Result<Vec<String>, TelegramError> {
+        let mut uploads = vec![];
+        if self.config.upload_images {
+            for image_url in image_urls {
+                uploads.push(self.download_image(image_url).await?);
+            }
         }
+        // Uploaded files are referred to by the name of their part in the request.
+        let attachments: Vec<String> = (0..uploads.len())
+            .map(|index| format!("attach://photo{}", index))
+            .collect();
+        let media_urls: Vec<&str> = if uploads.is_empty() {
+            image_urls.to_vec()
+        } else {
+            attachments.iter().map(String::as_str).collect()
+        };
+
+        let media_group = MediaGroup {
+            chat_id: &self.chat_id,
+            media: media_urls
+                .iter()
+                .enumerate()
+                .map(|(index, image_url)| {
+                    self.photo(image_url, if index == 0 { caption } else { None })
+                })
+                .collect(),
+            disable_notification: true,
+        };
+        let url = format!("{}/bot{}/sendMediaGroup", self.domain, self.token);
+        let resp = if uploads.is_empty() {
+            self.execute_send(|| Client::new().post(&url).json(&media_group))
+                .await?
+        } else {
+            let media = to_string(&media_group.media)?;
+            self.execute_send(|| {
+                let form = uploads.iter().enumerate().fold(
+                    Form::new()
+                        .text("chat_id", self.chat_id.clone())
+                        .text("media", media.clone())
+                        .text("disable_notification", "true"),
+                    |form, (index, upload)| form.part(format!("photo{}", index), upload.part()),
+                );
+                Client::new().post(&url).multipart(form)
+            })
+            .await?
+        };
+        let resp: Value = from_str(&resp)?;
+        let messages = resp["result"].as_array().cloned().unwrap_or_default();
+        Ok(messages
+            .iter()
+            .map(|message| format!("{}", message["message_id"]))
+            .collect())
+    }
+
+    pub async fn edit_message_caption(
+        &self,
+        message_id: &str,
+        caption: &str,
+    ) -> Result<(), TelegramError> {
+        let url = format!("{}/bot{}/editMessageCaption", self.domain, self.token);
+        let form = self.formatted_form(message_id, "caption", caption);
+        self.execute(|| Client::new().post(&url).form(&form))
+            .await?;
+        Ok(())
     }
 
     pub async fn edit_message_image(
         &self,
         message_id: &str,
         image_url: &str,
-    ) -> Result<(), Box<dyn Error>> {
+        caption: Option<&str>,
+    ) -> Result<(), TelegramError> {
         let url = format!("{}/bot{}/editMessageMedia", self.domain, self.token);
-        let body: serde_json::Value = serde_json::from_str(
-            format!(
-                r#"
-        {{
-            "chat_id": "{}",
-            "message_id": "{}",
-            "media": {{
-                "type": "photo",
-                "media": "{}"
-            }}
-        }}
-        "#,
-                &self.chat_id, message_id, image_url
-            )
-                .as_str(),
-        )
-            .unwrap();
-        let resp: Response = Client::new().post(&url).json(&body).send().await?;
-
-        if resp.status().is_success() {
-            Ok(())
-        } else {
-            Err(resp.text().await?.into())
+        if self.config.upload_images {
+            let upload = self.download_image(image_url).await?;
+            let media = to_string(&self.photo("attach://photo", caption))?;
+            self.execute(|| {
+                let form = Form::new()
+                    .text("chat_id", self.chat_id.clone())
+                    .text("message_id", String::from(message_id))
+                    .text("media", media.clone())
+                    .part("photo", upload.part());
+                Client::new().post(&url).multipart(form)
+            })
+            .await?;
+            return Ok(());
         }
+
+        let body = EditMedia {
+            chat_id: &self.chat_id,
+            message_id,
+            media: self.photo(image_url, caption),
+        };
+        self.execute(|| Client::new().post(&url).json(&body))
+            .await?;
+        Ok(())
+    }
+}
+
+// Names an uploaded image after the last segment of its URL path.
+fn file_name(image_url: &str) -> String {
+    image_url
+        .split('?')
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .unwrap_or_else(|| String::from("image.jpg"))
+}
+
+async fn send(request: RequestBuilder) -> Result<String, TelegramError> {
+    let resp = request.send().await?;
+    let status = resp.status();
+    if status.is_success() {
+        Ok(resp.text().await?)
+    } else {
+        Err(TelegramError::from_response(
+            status.as_u16(),
+            resp.text().await?,
+        ))
+    }
+}
+#[async_trait]
+impl Publisher for TelegramClient {
+    async fn publish_text(&self, text: &str) -> Result<String, Error> {
+        let text = render(text, self.config.parse_mode);
+        Ok(self.send_message(&text).await?)
+    }
+
+    async fn publish_image(&self, image_url: &str) -> Result<String, Error> {
+        Ok(self.send_image(image_url).await?)
+    }
+
+    async fn publish_album(
+        &self,
+        image_urls: &[&str],
+        caption: Option<&str>,
+    ) -> Result<Vec<String>, Error> {
+        let caption = caption.map(|caption| render(caption, self.config.parse_mode));
+        Ok(self
+            .send_media_group(image_urls, caption.as_deref())
+            .await?)
+    }
+
+    async fn edit_text(&self, message_id: &str, text: &str) -> Result<(), Error> {
+        let text = render(text, self.config.parse_mode);
+        Ok(self.edit_message_text(message_id, &text).await?)
+    }
+
+    async fn edit_caption(&self, message_id: &str, caption: &str) -> Result<(), Error> {
+        let caption = render(caption, self.config.parse_mode);
+        Ok(self.edit_message_caption(message_id, &caption).await?)
+    }
+
+    async fn replace_image(
+        &self,
+        message_id: &str,
+        image_url: &str,
+        caption: Option<&str>,
+    ) -> Result<(), Error> {
+        let caption = caption.map(|caption| render(caption, self.config.parse_mode));
+        Ok(self
+            .edit_message_image(message_id, image_url, caption.as_deref())
+            .await?)
+    }
+
+    async fn delete(&self, message_id: &str) -> Result<(), Error> {
+        Ok(self.delete_message(message_id).await?)
     }
 }
 
@@ -163,6 +494,7 @@ mod tests {
     use serde_json::json;
 
     use super::*;
+    use crate::formatter::ParseMode;
 
     const TOKEN: &str = "token";
     const CHAT_ID: &str = "123";
@@ -182,6 +514,7 @@ mod tests {
         let expected_message = Message {
             chat_id: CHAT_ID,
             text,
+            parse_mode: Some("HTML"),
             disable_notification: true,
         };
 
@@ -212,6 +545,7 @@ mod tests {
         let expected_message = Message {
             chat_id: CHAT_ID,
             text,
+            parse_mode: Some("HTML"),
             disable_notification: true,
         };
 
@@ -229,8 +563,7 @@ mod tests {
         );
 
         let result = client.send_message(text).await.unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert!(matches!(result, TelegramError::ChatNotFound));
         _m.assert();
     }
 
@@ -260,7 +593,6 @@ mod tests {
         );
 
         let result = client.send_image(image_url).await.unwrap();
-        let result = format!("{}", result);
         assert_eq!(result, "691");
         _m.assert();
     }
@@ -291,8 +623,65 @@ mod tests {
         );
 
         let result = client.send_image(image_url).await.unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert!(matches!(result, TelegramError::ChatNotFound));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_media_group_success() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":[{"message_id":691,"chat":{"id":123,"type":"channel"},"date":1581200384,"media_group_id":"1","photo":[]},{"message_id":692,"chat":{"id":123,"type":"channel"},"date":1581200384,"media_group_id":"1","photo":[]}]}"#;
+
+        let _m = mock("POST", format!("/bot{}/sendMediaGroup", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "chat_id": CHAT_ID,
+                "media": [
+                    {"type": "photo", "media": "image-1", "caption": "caption", "parse_mode": "HTML"},
+                    {"type": "photo", "media": "image-2"}
+                ],
+                "disable_notification": true
+            })))
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(
+            String::from(TOKEN),
+            String::from(CHAT_ID),
+            String::from(url),
+        );
+
+        let result = client
+            .send_media_group(&["image-1", "image-2"], Some("caption"))
+            .await
+            .unwrap();
+        assert_eq!(result, vec!["691", "692"]);
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_media_group_error() {
+        let error = r#"{"ok":false,"error_code":400,"description":"Bad Request: chat not found"}"#;
+        let url = &server_url();
+
+        let _m = mock("POST", format!("/bot{}/sendMediaGroup", TOKEN).as_str())
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .create();
+
+        let client = TelegramClient::new_with(
+            String::from(TOKEN),
+            String::from(CHAT_ID),
+            String::from(url),
+        );
+
+        let result = client
+            .send_media_group(&["image-1", "image-2"], None)
+            .await
+            .unwrap_err();
+        assert!(matches!(result, TelegramError::ChatNotFound));
         _m.assert();
     }
 
@@ -310,8 +699,7 @@ mod tests {
             String::from(url),
         );
 
-        let result = client.delete_message("id").await.unwrap();
-        assert_eq!(result, ());
+        client.delete_message("id").await.unwrap();
         _m.assert();
     }
 
@@ -333,8 +721,7 @@ mod tests {
         );
 
         let result = client.delete_message("id").await.unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert!(matches!(result, TelegramError::ChatNotFound));
         _m.assert();
     }
 
@@ -349,6 +736,7 @@ mod tests {
                 Matcher::UrlEncoded(String::from("chat_id"), String::from(CHAT_ID)),
                 Matcher::UrlEncoded(String::from("message_id"), String::from(message_id)),
                 Matcher::UrlEncoded(String::from("text"), String::from(text)),
+                Matcher::UrlEncoded(String::from("parse_mode"), String::from("HTML")),
             ]))
             .with_status(200)
             .with_body("success")
@@ -361,8 +749,7 @@ mod tests {
             String::from(url),
         );
 
-        let result = client.edit_message_text(message_id, text).await.unwrap();
-        assert_eq!(result, ());
+        client.edit_message_text(message_id, text).await.unwrap();
         _m.assert();
     }
 
@@ -394,8 +781,38 @@ mod tests {
             .edit_message_text(message_id, text)
             .await
             .unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert!(matches!(result, TelegramError::ChatNotFound));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn edit_message_caption_success() {
+        let url = &server_url();
+        let caption = "caption";
+        let message_id = "id";
+
+        let _m = mock("POST", format!("/bot{}/editMessageCaption", TOKEN).as_str())
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded(String::from("chat_id"), String::from(CHAT_ID)),
+                Matcher::UrlEncoded(String::from("message_id"), String::from(message_id)),
+                Matcher::UrlEncoded(String::from("caption"), String::from(caption)),
+                Matcher::UrlEncoded(String::from("parse_mode"), String::from("HTML")),
+            ]))
+            .with_status(200)
+            .with_body("success")
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(
+            String::from(TOKEN),
+            String::from(CHAT_ID),
+            String::from(url),
+        );
+
+        client
+            .edit_message_caption(message_id, caption)
+            .await
+            .unwrap();
         _m.assert();
     }
 
@@ -436,11 +853,39 @@ mod tests {
             String::from(url),
         );
 
-        let result = client
-            .edit_message_image(message_id, image_url)
+        client
+            .edit_message_image(message_id, image_url, None)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn edit_message_image_with_caption_success() {
+        let url = &server_url();
+        let message_id = "id";
+
+        let _m = mock("POST", format!("/bot{}/editMessageMedia", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "chat_id": CHAT_ID,
+                "message_id": message_id,
+                "media": {"type": "photo", "media": "image-url", "caption": "caption", "parse_mode": "HTML"}
+            })))
+            .with_status(200)
+            .with_body("success")
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(
+            String::from(TOKEN),
+            String::from(CHAT_ID),
+            String::from(url),
+        );
+
+        client
+            .edit_message_image(message_id, "image-url", Some("caption"))
             .await
             .unwrap();
-        assert_eq!(result, ());
         _m.assert();
     }
 
@@ -484,11 +929,396 @@ mod tests {
         );
 
         let result = client
-            .edit_message_image(message_id, image_url)
+            .edit_message_image(message_id, image_url, None)
             .await
             .unwrap_err();
-        let result = format!("{}", result);
-        assert_eq!(result, error);
+        assert!(matches!(result, TelegramError::ChatNotFound));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn publish_text_renders_html() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":{"message_id":691}}"#;
+
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "chat_id": CHAT_ID,
+                "text": "<b>Mazais pusdienu piedāvājums:</b>\n🍲 zupa &amp; deserts &lt;3",
+                "parse_mode": "HTML",
+                "disable_notification": true,
+            })))
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(
+            String::from(TOKEN),
+            String::from(CHAT_ID),
+            String::from(url),
+        );
+
+        let result = client
+            .publish_text(" Mazais pusdienu piedāvājums:  \n🍲 zupa & deserts <3")
+            .await
+            .unwrap();
+        assert_eq!(result, "691");
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn publish_text_without_parse_mode() {
+        let url = &server_url();
+        let resp = r#"{"ok":true,"result":{"message_id":692}}"#;
+
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::Json(json!({
+                "chat_id": CHAT_ID,
+                "text": "Lielais pusdienu piedāvājums:\n💸 4,60€",
+                "disable_notification": true,
+            })))
+            .with_status(200)
+            .with_body(resp)
+            .with_header("content-type", "application/json")
+            .create();
+
+        let client = TelegramClient::new_with(
+            String::from(TOKEN),
+            String::from(CHAT_ID),
+            String::from(url),
+        )
+        .with_config(ClientConfig {
+            parse_mode: ParseMode::Plain,
+            ..ClientConfig::default()
+        });
+
+        let result = client
+            .publish_text("Lielais pusdienu piedāvājums:  \n💸 4,60€")
+            .await
+            .unwrap();
+        assert_eq!(result, "692");
+        _m.assert();
+    }
+
+    fn retrying_client(url: &str) -> TelegramClient {
+        TelegramClient::new_with(
+            String::from(TOKEN),
+            String::from(CHAT_ID),
+            String::from(url),
+        )
+        .with_config(ClientConfig {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_secs(1),
+            messages_per_minute: 600,
+            ..ClientConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn rate_limited_request_is_retried() {
+        let error = r#"{"ok":false,"error_code":429,"description":"Too Many Requests: retry after 0","parameters":{"retry_after":0}}"#;
+        let _m = mock("POST", format!("/bot{}/deleteMessage", TOKEN).as_str())
+            .with_status(429)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .expect(3)
+            .create();
+
+        let client = retrying_client(&server_url());
+        let result = client.delete_message("1").await.unwrap_err();
+        assert!(matches!(
+            result,
+            TelegramError::RateLimited { retry_after: 0 }
+        ));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn long_retry_after_is_not_waited_for() {
+        let error = r#"{"ok":false,"error_code":429,"description":"Too Many Requests: retry after 30","parameters":{"retry_after":30}}"#;
+        let _m = mock("POST", format!("/bot{}/deleteMessage", TOKEN).as_str())
+            .with_status(429)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .expect(1)
+            .create();
+
+        let client = retrying_client(&server_url());
+        let result = client.delete_message("1").await.unwrap_err();
+        assert!(matches!(
+            result,
+            TelegramError::RateLimited { retry_after: 30 }
+        ));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn server_error_is_retried() {
+        let error = r#"{"ok":false,"error_code":502,"description":"Bad Gateway"}"#;
+        let _m = mock("POST", format!("/bot{}/deleteMessage", TOKEN).as_str())
+            .with_status(502)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .expect(3)
+            .create();
+
+        let client = retrying_client(&server_url());
+        let result = client.delete_message("1").await.unwrap_err();
+        assert!(matches!(
+            result,
+            TelegramError::Api {
+                error_code: 502,
+                ..
+            }
+        ));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn network_error_is_retried() {
+        // Nothing listens on the discard port, so every attempt fails to connect.
+        let client = retrying_client("http://127.0.0.1:9");
+        let result = client.delete_message("1").await.unwrap_err();
+        assert!(matches!(result, TelegramError::NetworkError(_)));
+    }
+
+    #[tokio::test]
+    async fn server_error_page_is_retried() {
+        let _m = mock("POST", format!("/bot{}/editMessageText", TOKEN).as_str())
+            .match_body(Matcher::Regex("message_id=502".into()))
+            .with_status(502)
+            .with_header("content-type", "text/html")
+            .with_body("<html><body><h1>502 Bad Gateway</h1></body></html>")
+            .expect(3)
+            .create();
+
+        let client = retrying_client(&server_url());
+        let result = client.edit_message_text("502", "text").await.unwrap_err();
+        assert!(matches!(
+            result,
+            TelegramError::Api {
+                error_code: 502,
+                ..
+            }
+        ));
         _m.assert();
     }
+
+    #[tokio::test]
+    async fn server_error_is_not_retried_for_new_messages() {
+        let error = r#"{"ok":false,"error_code":502,"description":"Bad Gateway"}"#;
+        let _m = mock("POST", format!("/bot{}/sendMessage", TOKEN).as_str())
+            .match_body(Matcher::PartialJson(json!({"text": "retried?"})))
+            .with_status(502)
+            .with_header("content-type", "application/json")
+            .with_body(error)
+            .expect(1)
+            .create();
+
+        let client = retrying_client(&server_url());
+        let result = client.send_message("retried?").await.unwrap_err();
+        assert!(matches!(
+            result,
+            TelegramError::Api {
+                error_code: 502,
+                ..
+            }
+        ));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn failed_connection_is_retried_for_new_messages() {
+        let client = retrying_client("http://127.0.0.1:9");
+        let result = client.send_message("text").await.unwrap_err();
+        assert!(matches!(result, TelegramError::NetworkError(_)));
+        assert!(result.is_retryable_send());
+    }
+
+    #[test]
+    fn backoff_grows_up_to_max_delay() {
+        let client = TelegramClient::new(String::from(TOKEN), String::from(CHAT_ID)).with_config(
+            ClientConfig {
+                max_retries: 10,
+                base_delay: Duration::from_millis(100),
+                max_delay: Duration::from_secs(1),
+                messages_per_minute: 20,
+                ..ClientConfig::default()
+            },
+        );
+        for (attempt, expected) in [100, 200, 400, 800, 1000, 1000].iter().enumerate() {
+            let delay = client.backoff(attempt as u32);
+            let expected = Duration::from_millis(*expected);
+            assert!(delay >= expected / 2 && delay <= expected, "{:?}", delay);
+        }
+    }
+
+    fn uploading_client(url: &str) -> TelegramClient {
+        TelegramClient::new_with(
+            String::from(TOKEN),
+            String::from(CHAT_ID),
+            String::from(url),
+        )
+        .with_config(ClientConfig {
+            upload_images: true,
+            max_image_size: 16,
+            ..ClientConfig::default()
+        })
+    }
+
+    fn image_mock(content_type: &str, body: &str) -> mockito::Mock {
+        mock("GET", "/cdn/photo.jpg")
+            .match_query(Matcher::Any)
+            .with_status(200)
+            .with_header("content-type", content_type)
+            .with_body(body)
+            .create()
+    }
+
+    // Matches a multipart form field with the given value.
+    fn form_field(name: &str, value: &str) -> Matcher {
+        Matcher::Regex(format!(
+            r#"name="{}"(; filename="[^"]*")?\r\n(Content-Type: [^\r]*\r\n)?\r\n{}\r\n"#,
+            name,
+            regex::escape(value)
+        ))
+    }
+
+    #[tokio::test]
+    async fn send_image_upload_success() {
+        let resp = r#"{"ok":true,"result":{"message_id":692}}"#;
+        let image_url = format!("{}/cdn/photo.jpg?oe=5E9A1B2C", server_url());
+
+        let _image = image_mock("image/jpeg", "jpeg-bytes");
+        let _m = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
+            .match_body(Matcher::AllOf(vec![
+                form_field("chat_id", CHAT_ID),
+                form_field("photo", "jpeg-bytes"),
+                Matcher::Regex(String::from(r#"filename="photo.jpg""#)),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(resp)
+            .create();
+
+        let client = uploading_client(&server_url());
+        let result = client.send_image(&image_url).await.unwrap();
+        assert_eq!(result, "692");
+        _image.assert();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_image_upload_rejects_content_type() {
+        let image_url = format!("{}/cdn/photo.jpg", server_url());
+
+        let _image = image_mock("text/html; charset=utf-8", "<html></html>");
+        let _m = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
+            .expect(0)
+            .create();
+
+        let client = uploading_client(&server_url());
+        let result = client.send_image(&image_url).await.unwrap_err();
+        assert!(matches!(result, TelegramError::InvalidImage(_)));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_image_upload_rejects_large_image() {
+        let image_url = format!("{}/cdn/photo.jpg", server_url());
+
+        let _image = image_mock("image/jpeg", "jpeg-bytes-larger-than-the-limit");
+        let _m = mock("POST", format!("/bot{}/sendPhoto", TOKEN).as_str())
+            .expect(0)
+            .create();
+
+        let client = uploading_client(&server_url());
+        let result = client.send_image(&image_url).await.unwrap_err();
+        assert!(matches!(result, TelegramError::InvalidImage(_)));
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn send_image_upload_rejects_expired_url() {
+        let image_url = format!("{}/cdn/photo.jpg?oe=5E9A1B2C", server_url());
+
+        let _image = mock("GET", "/cdn/photo.jpg")
+            .match_query(Matcher::Any)
+            .with_status(403)
+            .with_body("URL signature expired")
+            .create();
+
+        let client = uploading_client(&server_url());
+        let result = client.send_image(&image_url).await.unwrap_err();
+        assert!(matches!(result, TelegramError::InvalidImage(_)));
+        _image.assert();
+    }
+
+    #[tokio::test]
+    async fn send_media_group_upload_success() {
+        let resp = r#"{"ok":true,"result":[{"message_id":693},{"message_id":694}]}"#;
+        let image_url = format!("{}/cdn/photo.jpg", server_url());
+
+        let _image = image_mock("image/png", "png-bytes").expect(2);
+        let _m = mock("POST", format!("/bot{}/sendMediaGroup", TOKEN).as_str())
+            .match_body(Matcher::AllOf(vec![
+                form_field(
+                    "media",
+                    r#"[{"type":"photo","media":"attach://photo0","caption":"caption","parse_mode":"HTML"},{"type":"photo","media":"attach://photo1"}]"#,
+                ),
+                form_field("photo0", "png-bytes"),
+                form_field("photo1", "png-bytes"),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(resp)
+            .create();
+
+        let client = uploading_client(&server_url());
+        let result = client
+            .send_media_group(&[&image_url, &image_url], Some("caption"))
+            .await
+            .unwrap();
+        assert_eq!(result, vec!["693", "694"]);
+        _image.assert();
+        _m.assert();
+    }
+
+    #[tokio::test]
+    async fn edit_message_image_upload_success() {
+        let resp = r#"{"ok":true,"result":{"message_id":692}}"#;
+        let image_url = format!("{}/cdn/photo.jpg", server_url());
+
+        let _image = image_mock("image/jpeg", "jpeg-bytes");
+        let _m = mock("POST", format!("/bot{}/editMessageMedia", TOKEN).as_str())
+            .match_body(Matcher::AllOf(vec![
+                form_field("message_id", "692"),
+                form_field("media", r#"{"type":"photo","media":"attach://photo"}"#),
+                form_field("photo", "jpeg-bytes"),
+            ]))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(resp)
+            .create();
+
+        let client = uploading_client(&server_url());
+        client
+            .edit_message_image("692", &image_url, None)
+            .await
+            .unwrap();
+        _m.assert();
+    }
+
+    #[test]
+    fn file_name_from_url() {
+        assert_eq!(
+            file_name(
+                "https://scontent.xx.fbcdn.net/v/t1.0-9/p526x296/1_n.jpg?_nc_cat=1&oe=5E9A1B2C"
+            ),
+            "1_n.jpg"
+        );
+        assert_eq!(file_name("https://example.com/"), "image.jpg");
+    }
 }
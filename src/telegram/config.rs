@@ -0,0 +1,73 @@
+use std::env;
+use std::time::Duration;
+
+use crate::formatter::ParseMode;
+
+/// Limits applied by `TelegramClient` to the calls it makes.
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    /// How many times a failed call is repeated before giving up.
+    pub max_retries: u32,
+    /// Delay before the first retry of a transient failure, doubled on every next one.
+    pub base_delay: Duration,
+    /// Longest delay worth waiting for, including the one requested by a rate limited response.
+    pub max_delay: Duration,
+    /// How many calls may be made to the chat per minute.
+    pub messages_per_minute: u32,
+    /// Download images and upload them to Telegram, instead of letting Telegram fetch the URL.
+    pub upload_images: bool,
+    /// Largest image in bytes that is uploaded.
+    pub max_image_size: u64,
+    /// Markup post text is rendered into.
+    pub parse_mode: ParseMode,
+}
+
+impl Default for ClientConfig {
+    fn default() -> ClientConfig {
+        ClientConfig {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(60),
+            // Telegram allows about 20 messages per minute to the same group or channel.
+            messages_per_minute: 20,
+            upload_images: false,
+            // Telegram does not accept photos larger than 10 MB.
+            max_image_size: 10 * 1024 * 1024,
+            parse_mode: ParseMode::Html,
+        }
+    }
+}
+
+impl ClientConfig {
+    pub fn from_env() -> ClientConfig {
+        let mut config = ClientConfig::default();
+        if let Ok(max_retries) = env::var("TG_MAX_RETRIES") {
+            config.max_retries = max_retries
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid TG_MAX_RETRIES env var: {}", max_retries));
+        }
+        if let Ok(messages_per_minute) = env::var("TG_MESSAGES_PER_MINUTE") {
+            config.messages_per_minute = match messages_per_minute.parse() {
+                Ok(messages_per_minute) if messages_per_minute > 0 => messages_per_minute,
+                _ => panic!(
+                    "Invalid TG_MESSAGES_PER_MINUTE env var: {}",
+                    messages_per_minute
+                ),
+            };
+        }
+        if let Ok(upload_images) = env::var("TG_UPLOAD_IMAGES") {
+            config.upload_images = upload_images
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid TG_UPLOAD_IMAGES env var: {}", upload_images));
+        }
+        if let Ok(parse_mode) = env::var("TG_PARSE_MODE") {
+            config.parse_mode = match parse_mode.to_lowercase().as_str() {
+                "plain" => ParseMode::Plain,
+                "html" => ParseMode::Html,
+                "markdownv2" => ParseMode::MarkdownV2,
+                _ => panic!("Invalid TG_PARSE_MODE env var: {}", parse_mode),
+            };
+        }
+        config
+    }
+}
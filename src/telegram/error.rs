@@ -2,14 +2,113 @@ use std::error::Error;
 use std::fmt;
 
 use reqwest::Error as ReqwestError;
-use serde::export::Formatter;
+use serde::Deserialize;
 use serde_json::error::Error as SerdeError;
 
 #[derive(Debug)]
 pub enum TelegramError {
     NetworkError(ReqwestError),
     MalformedResponse(SerdeError),
+    /// Too many requests, the call may be repeated after `retry_after` seconds.
+    RateLimited {
+        retry_after: u64,
+    },
+    /// The edit would leave the message exactly as it is.
+    NotModified,
+    /// The message to edit or delete no longer exists.
+    MessageNotFound,
+    ChatNotFound,
+    /// The bot is not allowed to post to the chat, e.g. it was removed from the channel.
+    Forbidden(String),
+    Api {
+        error_code: i64,
+        description: String,
+    },
     Unsuccessful(String),
+    /// An image to upload could not be downloaded, or is not one Telegram would accept.
+    InvalidImage(String),
+}
+
+impl TelegramError {
+    /// Builds the error of an unsuccessful response. A server error without the usual JSON body,
+    /// like the page of a failing proxy, keeps its status so that it is still retried.
+    pub fn from_response(status: u16, body: String) -> Self {
+        match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(response) => response.into(),
+            Err(_) if status >= 500 => TelegramError::Api {
+                error_code: i64::from(status),
+                description: body,
+            },
+            Err(_) => TelegramError::Unsuccessful(body),
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            TelegramError::NetworkError(_) => true,
+            TelegramError::RateLimited { .. } => true,
+            TelegramError::Api { error_code, .. } => *error_code >= 500,
+            _ => false,
+        }
+    }
+
+    /// Whether a new message may be sent again without risking a duplicate, because the failed
+    /// attempt never reached Telegram or was turned away by it.
+    pub fn is_retryable_send(&self) -> bool {
+        match self {
+            TelegramError::NetworkError(error) => is_connect(error),
+            TelegramError::RateLimited { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+// reqwest only tells a failure to connect apart from one in the middle of a request through the
+// hyper error behind it.
+fn is_connect(error: &ReqwestError) -> bool {
+    error
+        .source()
+        .and_then(|source| source.downcast_ref::<hyper::Error>())
+        .is_some_and(hyper::Error::is_connect)
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error_code: i64,
+    description: String,
+    parameters: Option<ResponseParameters>,
+}
+
+#[derive(Deserialize)]
+struct ResponseParameters {
+    retry_after: Option<u64>,
+}
+
+impl From<ErrorResponse> for TelegramError {
+    fn from(response: ErrorResponse) -> Self {
+        let retry_after = response
+            .parameters
+            .and_then(|parameters| parameters.retry_after);
+        let description = response.description.to_lowercase();
+        match response.error_code {
+            429 => TelegramError::RateLimited {
+                retry_after: retry_after.unwrap_or(1),
+            },
+            403 => TelegramError::Forbidden(response.description),
+            400 if description.contains("message is not modified") => TelegramError::NotModified,
+            400 if description.contains("message to edit not found")
+                || description.contains("message to delete not found")
+                || description.contains("message_id_invalid") =>
+            {
+                TelegramError::MessageNotFound
+            }
+            400 if description.contains("chat not found") => TelegramError::ChatNotFound,
+            error_code => TelegramError::Api {
+                error_code,
+                description: response.description,
+            },
+        }
+    }
 }
 
 impl From<ReqwestError> for TelegramError {
@@ -24,20 +123,145 @@ impl From<SerdeError> for TelegramError {
     }
 }
 
-impl From<String> for TelegramError {
-    fn from(error: String) -> Self {
-        TelegramError::Unsuccessful(error)
-    }
-}
-
 impl Error for TelegramError {}
 
 impl fmt::Display for TelegramError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             TelegramError::NetworkError(err) => err.fmt(f),
             TelegramError::MalformedResponse(err) => err.fmt(f),
+            TelegramError::RateLimited { retry_after } => {
+                write!(f, "rate limited, retry after {} seconds", retry_after)
+            }
+            TelegramError::NotModified => write!(f, "message is not modified"),
+            TelegramError::MessageNotFound => write!(f, "message not found"),
+            TelegramError::ChatNotFound => write!(f, "chat not found"),
+            TelegramError::Forbidden(description) => description.fmt(f),
+            TelegramError::Api {
+                error_code,
+                description,
+            } => write!(f, "{}: {}", error_code, description),
             TelegramError::Unsuccessful(err) => err.fmt(f),
+            TelegramError::InvalidImage(reason) => write!(f, "invalid image: {}", reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(body: &str) -> TelegramError {
+        TelegramError::from_response(400, String::from(body))
+    }
+
+    #[test]
+    fn parses_rate_limit() {
+        let error = parse(
+            r#"{"ok":false,"error_code":429,"description":"Too Many Requests: retry after 35","parameters":{"retry_after":35}}"#,
+        );
+        match error {
+            TelegramError::RateLimited { retry_after } => assert_eq!(retry_after, 35),
+            _ => panic!("unexpected error: {:?}", error),
+        }
+        assert!(error.is_retryable());
+        assert!(error.is_retryable_send());
+    }
+
+    #[test]
+    fn parses_not_modified() {
+        let error = parse(
+            r#"{"ok":false,"error_code":400,"description":"Bad Request: message is not modified: specified new message content and reply markup are exactly the same as a current content and reply markup of the message"}"#,
+        );
+        assert!(matches!(error, TelegramError::NotModified));
+    }
+
+    #[test]
+    fn parses_message_not_found() {
+        for description in &[
+            "Bad Request: message to edit not found",
+            "Bad Request: message to delete not found",
+            "Bad Request: MESSAGE_ID_INVALID",
+        ] {
+            let error = parse(&format!(
+                r#"{{"ok":false,"error_code":400,"description":"{}"}}"#,
+                description
+            ));
+            assert!(
+                matches!(error, TelegramError::MessageNotFound),
+                "{}",
+                description
+            );
+        }
+    }
+
+    #[test]
+    fn parses_chat_not_found() {
+        let error =
+            parse(r#"{"ok":false,"error_code":400,"description":"Bad Request: chat not found"}"#);
+        assert!(matches!(error, TelegramError::ChatNotFound));
+        assert!(!error.is_retryable());
+        assert!(!error.is_retryable_send());
+    }
+
+    #[test]
+    fn parses_forbidden() {
+        let error = parse(
+            r#"{"ok":false,"error_code":403,"description":"Forbidden: bot is not a member of the channel chat"}"#,
+        );
+        match error {
+            TelegramError::Forbidden(description) => {
+                assert_eq!(
+                    description,
+                    "Forbidden: bot is not a member of the channel chat"
+                )
+            }
+            _ => panic!("unexpected error: {:?}", error),
+        }
+    }
+
+    #[test]
+    fn parses_other_api_errors() {
+        let error = parse(
+            r#"{"ok":false,"error_code":400,"description":"Bad Request: wrong file identifier/HTTP URL specified"}"#,
+        );
+        match error {
+            TelegramError::Api {
+                error_code,
+                ref description,
+            } => {
+                assert_eq!(error_code, 400);
+                assert_eq!(
+                    description,
+                    "Bad Request: wrong file identifier/HTTP URL specified"
+                );
+            }
+            _ => panic!("unexpected error: {:?}", error),
+        }
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn keeps_unparseable_body() {
+        let error = parse("Bad Request");
+        assert_eq!(format!("{}", error), "Bad Request");
+        assert!(!error.is_retryable());
+    }
+
+    #[test]
+    fn keeps_status_of_unparseable_server_error() {
+        let error = TelegramError::from_response(502, String::from("<html>Bad Gateway</html>"));
+        match error {
+            TelegramError::Api {
+                error_code,
+                ref description,
+            } => {
+                assert_eq!(error_code, 502);
+                assert_eq!(description, "<html>Bad Gateway</html>");
+            }
+            _ => panic!("unexpected error: {:?}", error),
         }
+        assert!(error.is_retryable());
+        assert!(!error.is_retryable_send());
     }
 }
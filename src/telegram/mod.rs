@@ -1,2 +1,4 @@
 pub mod client;
+pub mod config;
 pub mod error;
+pub mod rate_limiter;
@@ -0,0 +1,77 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::time::delay_for;
+
+/// Token bucket that lets bursts of up to `capacity` calls through and refills at a steady rate.
+pub struct RateLimiter {
+    capacity: f64,
+    per_second: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, per_second: f64) -> RateLimiter {
+        RateLimiter {
+            capacity: f64::from(capacity),
+            per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(capacity),
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn per_minute(messages: u32) -> RateLimiter {
+        RateLimiter::new(messages, f64::from(messages) / 60.0)
+    }
+
+    /// Waits until a call may be made.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.capacity);
+                bucket.updated = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_second)
+            };
+            delay_for(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lets_burst_through() {
+        let limiter = RateLimiter::new(3, 1.0);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn waits_for_refill() {
+        let limiter = RateLimiter::new(1, 20.0);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -16,16 +16,16 @@ async fn process_posts_success() {
     let dynamo_client = DynamoClient::new(table_name);
     let telegram_client = TelegramClient::new(token, chat_id);
 
-    let posts = dynamo_client.scan_posts().await.unwrap();
+    let posts = dynamo_client.scan_posts(0).await.unwrap();
     delete_posts(&dynamo_client, &posts).await;
 
     process_posts().await.unwrap();
-    let posts = dynamo_client.scan_posts().await.unwrap();
+    let posts = dynamo_client.scan_posts(0).await.unwrap();
     assert_eq!(18, posts.len());
 
     // Running second time skips posts that are already sent
     process_posts().await.unwrap();
-    let posts = dynamo_client.scan_posts().await.unwrap();
+    let posts = dynamo_client.scan_posts(0).await.unwrap();
     assert_eq!(18, posts.len());
 
     delete_posts(&dynamo_client, &posts).await;
@@ -37,21 +37,29 @@ async fn delete_posts(client: &DynamoClient, posts: &[sources::Post]) {
         client.delete_post(&post.id).await.unwrap();
         sleep(Duration::from_millis(200)); // Throttling to avoid hitting limits
     }
-    let posts = client.scan_posts().await.unwrap();
+    let posts = client.scan_posts(0).await.unwrap();
     assert_eq!(0, posts.len());
 }
 
 async fn delete_messages(client: &TelegramClient, posts: &[sources::Post]) {
+    // An album's caption is the message of its first image, so ids are deleted only once.
+    let mut message_ids: Vec<&String> = vec![];
     for post in posts {
+        let ids = post
+            .tg_id
+            .iter()
+            .chain(post.images.iter().filter_map(|image| image.tg_id.as_ref()))
+            .chain(post.continuation_ids.iter());
+        for id in ids {
+            if !message_ids.contains(&id) {
+                message_ids.push(id);
+            }
+        }
+    }
+    for message_id in message_ids {
         client
-            .delete_message(&post.tg_id.as_ref().unwrap())
+            .delete_message(message_id)
             .await
             .expect("Failed to delete message");
-        for sources::Image { url: _url, tg_id } in &post.images {
-            client
-                .delete_message(&tg_id.as_ref().unwrap())
-                .await
-                .expect("Failed to delete image");
-        }
     }
 }